@@ -0,0 +1,172 @@
+use crate::diskspace::DiskSpaceOptions;
+use crate::filename::FilenameOptions;
+use crate::metadata_cache;
+use crate::sites::{self, SiteConfig};
+use crate::staging;
+use crate::youtube::{DownloadHistory, DownloadRequest, ProgressCallback, VideoInfo, YouTubeDownloader};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    YtDlpCli,
+    YtDlpCrate,
+}
+
+/// yt-dlp and ffmpeg locations, resolved once and handed to backends so they
+/// don't search `PATH` on every call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Binaries {
+    pub yt_dlp: PathBuf,
+    pub ffmpeg: PathBuf,
+    pub yt_dlp_version: Option<String>,
+}
+
+impl Binaries {
+    pub fn resolve() -> Result<Self> {
+        let yt_dlp = YouTubeDownloader::find_yt_dlp_path()?;
+        Ok(Self {
+            yt_dlp_version: YouTubeDownloader::query_yt_dlp_version(&yt_dlp),
+            yt_dlp,
+            ffmpeg: YouTubeDownloader::find_ffmpeg_path()?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct BackendOptions {
+    pub output_dir: PathBuf,
+    pub filename_options: FilenameOptions,
+    pub staging_root: PathBuf,
+    pub disk_space: DiskSpaceOptions,
+    pub progress_callback: Option<ProgressCallback>,
+    pub metadata_ttl: Duration,
+    pub sites: Vec<SiteConfig>,
+    pub binaries: Option<Binaries>,
+    /// Whether links to plain media files may be fetched without yt-dlp.
+    pub direct_downloads: bool,
+}
+
+impl BackendOptions {
+    pub fn new(output_dir: PathBuf) -> Self {
+        Self {
+            output_dir,
+            filename_options: FilenameOptions::default(),
+            staging_root: staging::default_staging_root(),
+            disk_space: DiskSpaceOptions::default(),
+            progress_callback: None,
+            metadata_ttl: metadata_cache::DEFAULT_METADATA_TTL,
+            sites: sites::default_sites(),
+            binaries: None,
+            direct_downloads: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormatInfo {
+    pub format_id: String,
+    pub ext: String,
+    #[serde(default)]
+    pub resolution: Option<String>,
+    #[serde(default)]
+    pub height: Option<u64>,
+    #[serde(default)]
+    pub fps: Option<f64>,
+    #[serde(default)]
+    pub vcodec: Option<String>,
+    #[serde(default)]
+    pub acodec: Option<String>,
+    #[serde(default)]
+    pub filesize: Option<u64>,
+    #[serde(default)]
+    pub tbr: Option<f64>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl FormatInfo {
+    pub fn from_json(format: &Value) -> Option<Self> {
+        let text = |key: &str| format[key].as_str().map(|s| s.to_string());
+        let codec = |key: &str| text(key).filter(|c| c != "none");
+        Some(Self {
+            format_id: text("format_id")?,
+            ext: text("ext").unwrap_or_default(),
+            resolution: text("resolution"),
+            height: format["height"].as_u64(),
+            fps: format["fps"].as_f64(),
+            vcodec: codec("vcodec"),
+            acodec: codec("acodec"),
+            filesize: format["filesize"]
+                .as_u64()
+                .or_else(|| format["filesize_approx"].as_u64()),
+            tbr: format["tbr"].as_f64(),
+            note: text("format_note"),
+        })
+    }
+
+    pub fn list_from_video_data(video_data: &Value) -> Vec<Self> {
+        video_data["formats"]
+            .as_array()
+            .map(|formats| formats.iter().filter_map(Self::from_json).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A way of turning a URL into media files. Every backend records its result
+/// as a `DownloadHistory` entry and reports progress through the callback in
+/// its `BackendOptions`; cancelled downloads come back with the `Cancelled`
+/// status rather than as an error.
+#[async_trait]
+pub trait MediaBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn check_dependencies(&self) -> Result<()>;
+
+    fn ffmpeg_path(&self) -> Result<PathBuf>;
+
+    async fn probe(&self, url: &str, refresh: bool) -> Result<VideoInfo>;
+
+    async fn list_formats(&self, url: &str) -> Result<Vec<FormatInfo>>;
+
+    /// Matches `url` against the supported sites list. A host the list does
+    /// not know is only looked up with the backend's extractor when `extract`
+    /// is set, as that costs a full metadata fetch.
+    async fn identify_site(&self, url: &str, extract: bool) -> Result<SiteConfig>;
+
+    /// Whether `url` points straight at a media file this backend would
+    /// fetch without an extractor. Backends without direct downloads, or
+    /// with them turned off, never claim a URL.
+    async fn probe_direct(&self, _url: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Details for `url` that are already known locally, such as metadata
+    /// cached by an earlier probe. Never touches the network.
+    fn cached_video_info(&self, _url: &str) -> Option<VideoInfo> {
+        None
+    }
+
+    async fn download(
+        &self,
+        request: DownloadRequest,
+        download_id: String,
+        cancel: CancellationToken,
+    ) -> Result<DownloadHistory>;
+}
+
+pub fn create_backend(kind: &BackendKind, options: BackendOptions) -> Result<Arc<dyn MediaBackend>> {
+    Ok(match kind {
+        BackendKind::YtDlpCli => Arc::new(YouTubeDownloader::from_options(options)?),
+        BackendKind::YtDlpCrate => Arc::new(crate::crate_backend::CrateBackend::new(options)?),
+    })
+}
@@ -0,0 +1,6 @@
+//! Stand-in for yt-dlp that serves calls recorded with `SNAPPER_RECORD_FIXTURES`
+//! back from the directory in `SNAPPER_REPLAY_FIXTURES`.
+
+fn main() {
+    std::process::exit(snapper_lib::fixtures::replay_main(std::env::args_os().skip(1)))
+}
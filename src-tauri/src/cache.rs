@@ -0,0 +1,269 @@
+use crate::metadata_cache;
+use crate::staging;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+const LEGACY_CACHE_DIR: &str = "snapper-cache";
+const PARTIAL_DIR: &str = "partial";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheInfo {
+    pub cache_root: String,
+    pub yt_dlp_cache_bytes: u64,
+    pub job_cache_bytes: u64,
+    pub metadata_cache_bytes: u64,
+    pub staging_bytes: u64,
+    pub legacy_cache_bytes: u64,
+    /// Unfinished direct downloads kept for resuming.
+    #[serde(default)]
+    pub partial_download_bytes: u64,
+    pub total_bytes: u64,
+    pub active_jobs: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheClearResult {
+    pub reclaimed_bytes: u64,
+    pub removed_entries: usize,
+    pub skipped_active: usize,
+    /// Staging folders kept because their files could not be moved out.
+    #[serde(default)]
+    pub skipped_kept: usize,
+}
+
+/// The folders Snapper caches data in. Everything below them was created by
+/// Snapper, so clearing the cache never reaches a user's own files.
+#[derive(Debug, Clone)]
+pub struct CacheDirs {
+    pub root: PathBuf,
+    /// Snapper's staging folder, from `staging::staging_root_in` when the user
+    /// configured a staging path.
+    pub staging: PathBuf,
+    pub legacy: PathBuf,
+}
+
+impl CacheDirs {
+    pub fn new(staging_root: &Path) -> Self {
+        Self {
+            root: cache_root(),
+            staging: staging_root.to_path_buf(),
+            legacy: legacy_cache_dir(),
+        }
+    }
+
+    fn jobs(&self) -> PathBuf {
+        self.root.join("jobs")
+    }
+
+    fn yt_dlp(&self) -> PathBuf {
+        self.root.join("yt-dlp")
+    }
+
+    fn metadata(&self) -> PathBuf {
+        self.root.join(metadata_cache::METADATA_DIR_NAME)
+    }
+
+    fn partials(&self) -> PathBuf {
+        self.root.join(PARTIAL_DIR)
+    }
+}
+
+fn active_jobs() -> &'static Mutex<HashSet<String>> {
+    static ACTIVE_JOBS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    ACTIVE_JOBS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+fn is_active(download_id: &str) -> bool {
+    active_jobs()
+        .lock()
+        .map(|jobs| jobs.contains(download_id))
+        .unwrap_or(true)
+}
+
+fn active_job_count() -> usize {
+    active_jobs().lock().map(|jobs| jobs.len()).unwrap_or(0)
+}
+
+pub fn cache_root() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("snapper")
+}
+
+pub fn yt_dlp_cache_dir() -> Result<PathBuf> {
+    let dir = cache_root().join("yt-dlp");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| anyhow!("Failed to create cache directory: {}", e))?;
+    Ok(dir)
+}
+
+pub fn partial_downloads_dir() -> Result<PathBuf> {
+    let dir = cache_root().join(PARTIAL_DIR);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| anyhow!("Failed to create partial downloads directory: {}", e))?;
+    Ok(dir)
+}
+
+fn jobs_root() -> PathBuf {
+    cache_root().join("jobs")
+}
+
+fn legacy_cache_dir() -> PathBuf {
+    std::env::temp_dir().join(LEGACY_CACHE_DIR)
+}
+
+pub struct JobCache {
+    download_id: String,
+    dir: PathBuf,
+}
+
+impl JobCache {
+    pub fn create(download_id: &str) -> Result<Self> {
+        Self::create_in(&jobs_root(), download_id)
+    }
+
+    pub fn create_in(jobs_root: &Path, download_id: &str) -> Result<Self> {
+        let dir = jobs_root.join(download_id);
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create job cache directory: {}", e))?;
+
+        if let Ok(mut jobs) = active_jobs().lock() {
+            jobs.insert(download_id.to_string());
+        }
+
+        Ok(Self {
+            download_id: download_id.to_string(),
+            dir,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for JobCache {
+    fn drop(&mut self) {
+        if self.dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+                eprintln!("Warning: Failed to remove job cache {:?}: {}", self.dir, e);
+            }
+        }
+        if let Ok(mut jobs) = active_jobs().lock() {
+            jobs.remove(&self.download_id);
+        }
+    }
+}
+
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| dir_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+pub fn cache_info(dirs: &CacheDirs) -> CacheInfo {
+    let yt_dlp_cache_bytes = dir_size(&dirs.yt_dlp());
+    let job_cache_bytes = dir_size(&dirs.jobs());
+    let metadata_cache_bytes = dir_size(&dirs.metadata());
+    let staging_bytes = dir_size(&dirs.staging);
+    let legacy_cache_bytes = dir_size(&dirs.legacy);
+    let partial_download_bytes = dir_size(&dirs.partials());
+
+    CacheInfo {
+        cache_root: dirs.root.to_string_lossy().to_string(),
+        yt_dlp_cache_bytes,
+        job_cache_bytes,
+        metadata_cache_bytes,
+        staging_bytes,
+        legacy_cache_bytes,
+        partial_download_bytes,
+        total_bytes: yt_dlp_cache_bytes
+            + job_cache_bytes
+            + metadata_cache_bytes
+            + staging_bytes
+            + legacy_cache_bytes
+            + partial_download_bytes,
+        active_jobs: active_job_count(),
+    }
+}
+
+pub fn clear_cache(dirs: &CacheDirs) -> CacheClearResult {
+    let mut result = CacheClearResult {
+        reclaimed_bytes: 0,
+        removed_entries: 0,
+        skipped_active: 0,
+        skipped_kept: 0,
+    };
+
+    // Jobs under this cache root still running share the yt-dlp cache and may
+    // be writing a partial download.
+    let running = std::fs::read_dir(dirs.jobs())
+        .map(|entries| {
+            entries
+                .flatten()
+                .any(|entry| is_active(&entry.file_name().to_string_lossy()))
+        })
+        .unwrap_or(false);
+
+    for root in [dirs.jobs(), dirs.staging.clone()] {
+        let Ok(entries) = std::fs::read_dir(&root) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_active(&name) {
+                result.skipped_active += 1;
+                continue;
+            }
+            if staging::is_kept(&entry.path()) {
+                result.skipped_kept += 1;
+                continue;
+            }
+            remove_entry(&entry.path(), &mut result);
+        }
+    }
+
+    if !running {
+        remove_entry(&dirs.yt_dlp(), &mut result);
+        remove_entry(&dirs.metadata(), &mut result);
+        metadata_cache::clear_memory();
+        remove_entry(&dirs.partials(), &mut result);
+        remove_entry(&dirs.legacy, &mut result);
+    } else {
+        println!("Downloads in progress, keeping shared yt-dlp cache and partial downloads");
+    }
+
+    result
+}
+
+fn remove_entry(path: &Path, result: &mut CacheClearResult) {
+    if !path.exists() {
+        return;
+    }
+
+    let size = dir_size(path);
+    let removed = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+
+    match removed {
+        Ok(_) => {
+            println!("Removed cache entry: {:?}", path);
+            result.reclaimed_bytes += size;
+            result.removed_entries += 1;
+        }
+        Err(e) => eprintln!("Failed to remove cache entry {:?}: {}", path, e),
+    }
+}
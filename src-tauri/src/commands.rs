@@ -1,108 +1,821 @@
+use crate::backend::{
+    self, BackendKind, BackendOptions, CancellationToken, FormatInfo, MediaBackend,
+};
+use crate::cache::{self, CacheClearResult, CacheDirs, CacheInfo};
+use crate::diskspace::{DiskSpaceOptions, DiskSpacePolicy};
+use crate::filename::{FilenameOptions, DEFAULT_FILENAME_TEMPLATE};
+use crate::history::{HistoryPage, HistoryQuery, HistoryStore, RetentionPolicy};
+use crate::hooks::{run_hooks, HookConfig};
+use crate::manager::{Cancellation, DownloadManager, QueueSnapshot, QueuedDownload};
+use crate::postprocess::{PostProcessPipeline, PostProcessProgress, PostProcessor};
+use crate::reconcile::{self, VerifyReport};
+use crate::security;
+use crate::sites::{self, SiteConfig};
+use crate::staging;
+use crate::stats::{self, DownloadStats, StatsQuery};
+use crate::urls::{self, UrlAnalysis};
+use crate::trash;
+use crate::webhooks::{self, WebhookConfig, WebhookEvent};
 use crate::youtube::{
-    DownloadHistory, DownloadRequest, DownloadStatus, VideoInfo, YouTubeDownloader,
+    CollisionPolicy, DownloadFailure, DownloadHistory, DownloadProgress, DownloadRequest,
+    DownloadStatus, ErrorCategory, ProgressCallback, VideoInfo,
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use tauri::AppHandle;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_opener::OpenerExt;
 use tauri_plugin_store::StoreExt;
 
 const STORE_FILE: &str = "download_history.json";
 const HISTORY_KEY: &str = "downloads";
 const SETTINGS_KEY: &str = "settings";
+const QUEUE_KEY: &str = "queue";
+
+const HISTORY_CHANGED_EVENT: &str = "history-changed";
+const QUEUE_CHANGED_EVENT: &str = "queue-changed";
+const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
 
 #[tauri::command]
-pub async fn validate_youtube_url(url: String) -> Result<bool, String> {
+pub async fn validate_youtube_url(url: String, app: AppHandle) -> Result<bool, String> {
+    let settings = current_settings(&app).await?;
     let output_dir = get_default_download_path().await?;
-    let downloader = YouTubeDownloader::new(PathBuf::from(output_dir))
-        .map_err(|e| format!("Failed to initialize downloader: {}", e))?;
+    let backend = create_backend(&settings, backend_options(&app, &settings, PathBuf::from(output_dir)))?;
 
-    Ok(downloader.validate_url(&url))
+    if backend.identify_site(&url, false).await.is_ok() {
+        return Ok(true);
+    }
+    Ok(matches!(backend.probe_direct(&url).await, Ok(true)))
 }
 
 #[tauri::command]
-pub async fn get_video_info(url: String) -> Result<VideoInfo, String> {
+pub async fn identify_site(
+    url: String,
+    extract: Option<bool>,
+    app: AppHandle,
+) -> Result<SiteConfig, String> {
+    let settings = current_settings(&app).await?;
     let output_dir = get_default_download_path().await?;
-    let downloader = YouTubeDownloader::new(PathBuf::from(output_dir))
-        .map_err(|e| format!("Failed to initialize downloader: {}", e))?;
+    let backend = create_backend(&settings, backend_options(&app, &settings, PathBuf::from(output_dir)))?;
 
-    downloader
-        .get_video_info(&url)
+    backend
+        .identify_site(&url, extract.unwrap_or(false))
+        .await
+        .map_err(|e| format!("Unsupported URL: {}", e))
+}
+
+#[tauri::command]
+pub async fn analyze_url(url: String) -> Result<UrlAnalysis, String> {
+    urls::analyze_url(&url).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_video_info(
+    url: String,
+    refresh: Option<bool>,
+    app: AppHandle,
+) -> Result<VideoInfo, String> {
+    let settings = current_settings(&app).await?;
+    let output_dir = get_default_download_path().await?;
+    let backend = create_backend(&settings, backend_options(&app, &settings, PathBuf::from(output_dir)))?;
+
+    backend
+        .probe(&url, refresh.unwrap_or(false))
         .await
         .map_err(|e| format!("Failed to get video info: {}", e))
 }
 
 #[tauri::command]
-pub async fn download_video(request: DownloadRequest, app: AppHandle) -> Result<String, String> {
-    let output_dir = PathBuf::from(&request.output_path);
-    let downloader = YouTubeDownloader::new(output_dir)
-        .map_err(|e| format!("Failed to initialize downloader: {}", e))?;
+pub async fn list_formats(url: String, app: AppHandle) -> Result<Vec<FormatInfo>, String> {
+    let settings = current_settings(&app).await?;
+    let output_dir = get_default_download_path().await?;
+    let backend = create_backend(&settings, backend_options(&app, &settings, PathBuf::from(output_dir)))?;
+
+    backend
+        .list_formats(&url)
+        .await
+        .map_err(|e| format!("Failed to list formats: {}", e))
+}
+
+#[tauri::command]
+pub async fn download_video(
+    request: DownloadRequest,
+    app: AppHandle,
+    manager: State<'_, DownloadManager>,
+) -> Result<String, String> {
+    queue_download(&app, &manager, request).await
+}
+
+async fn queue_download(
+    app: &AppHandle,
+    manager: &DownloadManager,
+    mut request: DownloadRequest,
+) -> Result<String, String> {
+    let settings = current_settings(app).await?;
+    request.collision_policy.get_or_insert(settings.collision_policy);
+    resolve_pipeline(&settings, request.pipeline.as_deref())?;
+    let output_dir = security::resolve_output_dir(&request.output_path, &approved_roots(&settings))
+        .map_err(|e| e.to_string())?;
 
     let download_id = uuid::Uuid::new_v4().to_string();
 
-    let initial_history = DownloadHistory {
+    // Until the download starts the URL stands in for the title, unless the
+    // video was already probed and its metadata is cached.
+    let mut initial_history = DownloadHistory {
         id: download_id.clone(),
-        title: "Pobieranie...".to_string(),
+        title: request.url.clone(),
         url: request.url.clone(),
-        status: DownloadStatus::Downloading,
+        status: DownloadStatus::Pending,
         downloaded_at: Utc::now(),
-        file_path: None,
         format: request.format.clone(),
         quality: request.quality.clone(),
+        ..Default::default()
     };
+    let cached_info = create_backend(&settings, backend_options(app, &settings, output_dir))
+        .ok()
+        .and_then(|backend| backend.cached_video_info(&request.url));
+    if let Some(info) = cached_info {
+        initial_history.apply_video_info(&info);
+    }
 
-    if let Err(e) = save_download_to_store(&app, &initial_history).await {
+    if let Err(e) = save_download_to_history(app, &initial_history).await {
         eprintln!("Failed to save initial download status: {}", e);
     }
 
-    let app_clone = app.clone();
-    let request_clone = request.clone();
-    let download_id_clone = download_id.clone();
-    let initial_history_clone = initial_history.clone();
+    manager.enqueue(&download_id, request);
+    start_queued_downloads(app);
 
-    tokio::spawn(async move {
-        println!("Starting download for ID: {}", download_id_clone);
-        let result = downloader.download_video(request_clone.clone(), download_id_clone.clone()).await;
+    Ok(download_id)
+}
 
-        match result {
-            Ok(history) => {
-                println!("Download successful for ID: {}, updating store", history.id);
-                if let Err(e) = save_download_to_store(&app_clone, &history).await {
-                    eprintln!("Failed to save download result: {}", e);
-                } else {
-                    println!("Successfully saved completed download to store");
-                }
+/// Starts queued downloads until the concurrency limit from settings is
+/// reached, then persists what is left waiting. Called whenever a download is
+/// queued or a running one ends.
+fn start_queued_downloads(app: &AppHandle) {
+    let manager = app.state::<DownloadManager>();
+    let max_concurrent = manager
+        .settings()
+        .map(|settings| settings.max_concurrent_downloads)
+        .unwrap_or_else(default_max_concurrent_downloads);
+
+    while let Some((job, cancel)) = manager.start_next(max_concurrent) {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let download_id = job.id.clone();
+            run_download(&app, job, cancel).await;
+            app.state::<DownloadManager>().finish(&download_id);
+            start_queued_downloads(&app);
+        });
+    }
+
+    if let Err(e) = save_queue_to_store(app, &manager.queued()) {
+        eprintln!("Failed to save download queue: {}", e);
+    }
+}
+
+async fn run_download(app: &AppHandle, job: QueuedDownload, cancel: CancellationToken) {
+    let QueuedDownload { id: download_id, request, .. } = job;
+    println!("Starting download for ID: {}", download_id);
+
+    let settings = match current_settings(app).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Failed to load settings for download {}: {}", download_id, e);
+            AppSettings::default()
+        }
+    };
+
+    let prepared = resolve_pipeline(&settings, request.pipeline.as_deref()).and_then(|pipeline| {
+        let output_dir = security::resolve_output_dir(&request.output_path, &approved_roots(&settings))
+            .map_err(|e| e.to_string())?;
+        let mut options = backend_options(app, &settings, output_dir);
+        options.progress_callback = Some(progress_emitter(app));
+        Ok((pipeline, create_backend(&settings, options)?))
+    });
+
+    let mut history = history_store(app)
+        .get(&download_id)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| DownloadHistory {
+            id: download_id.clone(),
+            title: request.url.clone(),
+            url: request.url.clone(),
+            format: request.format.clone(),
+            quality: request.quality.clone(),
+            ..Default::default()
+        });
+    history.status = DownloadStatus::Downloading;
+    history.downloaded_at = Utc::now();
+    history.started_at = Some(history.downloaded_at);
+    history.finished_at = None;
+    history.error = None;
+    history.attempts += 1;
+    if let Err(e) = save_download_to_history(app, &history).await {
+        eprintln!("Failed to save download status: {}", e);
+    }
+    webhooks::dispatch(&settings.webhooks, WebhookEvent::Started, &history, None);
+
+    let finished = complete_download(prepared, request, history, &settings, cancel, |progress| {
+        if let Err(e) = app.emit("postprocess-progress", &progress) {
+            eprintln!("Failed to emit post-processing progress: {}", e);
+        }
+    })
+    .await;
+
+    if let Err(e) = save_download_to_history(app, &finished).await {
+        eprintln!("Failed to save download result: {}", e);
+    } else {
+        println!("Successfully saved download result to store");
+    }
+}
+
+/// Runs a started download on its backend, then post-processing, hooks and
+/// webhooks, and returns the entry to save. When preparing the backend failed
+/// or the backend reports an error, `started` comes back marked failed. Needs
+/// no `AppHandle`, so it can be driven by any `MediaBackend`.
+pub(crate) async fn complete_download<F>(
+    prepared: Result<(Option<PostProcessPipeline>, Arc<dyn MediaBackend>), String>,
+    request: DownloadRequest,
+    mut started: DownloadHistory,
+    settings: &AppSettings,
+    cancel: CancellationToken,
+    on_postprocess_progress: F,
+) -> DownloadHistory
+where
+    F: Fn(PostProcessProgress),
+{
+    let collision_policy = request.collision_policy.unwrap_or_default();
+    let result = match prepared {
+        Ok((pipeline, backend)) => backend
+            .download(request, started.id.clone(), cancel)
+            .await
+            .map(|history| (pipeline, backend, history))
+            .map_err(|e| DownloadFailure::from_error(&e)),
+        Err(e) => Err(DownloadFailure::from_message(&e)),
+    };
+
+    match result {
+        Ok((pipeline, backend, mut completed)) => {
+            completed.started_at = started.started_at;
+            completed.attempts = started.attempts;
+            if let Some(pipeline) = &pipeline {
+                run_post_processing(
+                    backend.as_ref(),
+                    pipeline,
+                    collision_policy,
+                    &mut completed,
+                    on_postprocess_progress,
+                )
+                .await;
             }
-            Err(e) => {
-                eprintln!("Download failed for ID {}: {}", download_id_clone, e);
-
-                let failed_history = DownloadHistory {
-                    id: download_id_clone.clone(),
-                    title: "Błąd pobierania".to_string(),
-                    url: initial_history_clone.url,
-                    status: DownloadStatus::Failed,
-                    downloaded_at: Utc::now(),
-                    file_path: None,
-                    format: initial_history_clone.format,
-                    quality: initial_history_clone.quality,
-                };
-
-                if let Err(e) = save_download_to_store(&app_clone, &failed_history).await {
-                    eprintln!("Failed to save failed download status: {}", e);
-                } else {
-                    println!("Successfully saved failed download to store");
-                }
+            completed.record_finish();
+            completed.hook_results = run_hooks(&settings.hooks, &completed).await;
+            if let Some(event) = WebhookEvent::for_status(&completed.status) {
+                webhooks::dispatch(&settings.webhooks, event, &completed, None);
             }
+
+            println!("Download finished for ID: {}", completed.id);
+            completed
         }
-    });
+        Err(failure) => {
+            eprintln!("Download failed for ID {}: {}", started.id, failure.message);
 
-    Ok(download_id)
+            started.status = DownloadStatus::Failed;
+            started.downloaded_at = Utc::now();
+            started.record_finish();
+            let message = failure.message.clone();
+            started.error = Some(failure);
+            started.hook_results = run_hooks(&settings.hooks, &started).await;
+            webhooks::dispatch(&settings.webhooks, WebhookEvent::Failed, &started, Some(message));
+            started
+        }
+    }
+}
+
+/// Queues downloads that were still waiting when the app last closed, then
+/// fails the history entries of any that were running at the time.
+pub async fn restore_download_queue(app: &AppHandle) {
+    let queued = match load_queue_from_store(app) {
+        Ok(queued) => queued,
+        Err(e) => {
+            eprintln!("Failed to restore download queue: {}", e);
+            Vec::new()
+        }
+    };
+    if queued.is_empty() {
+        fail_interrupted_downloads(app);
+        return;
+    }
+
+    println!("Restoring {} queued downloads", queued.len());
+    if let Err(e) = current_settings(app).await {
+        eprintln!("Failed to load settings for queued downloads: {}", e);
+    }
+    let manager = app.state::<DownloadManager>();
+    for job in queued {
+        manager.enqueue(&job.id, job.request);
+    }
+    fail_interrupted_downloads(app);
+    start_queued_downloads(app);
+}
+
+/// Entries still marked active with no job behind them belong to downloads
+/// the app was closed or crashed during. Their request is not persisted, so
+/// they are failed rather than retried.
+fn fail_interrupted_downloads(app: &AppHandle) {
+    let manager = app.state::<DownloadManager>();
+    let store = history_store(app);
+    let active = match store.find_active() {
+        Ok(active) => active,
+        Err(e) => {
+            eprintln!("Failed to look up interrupted downloads: {}", e);
+            return;
+        }
+    };
+
+    for entry in active.into_iter().filter(|entry| !manager.has_job(&entry.id)) {
+        println!("Marking interrupted download as failed: {}", entry.id);
+        let updated = store.update(&entry.id, |entry| {
+            entry.status = DownloadStatus::Failed;
+            entry.record_finish();
+            entry.error = Some(DownloadFailure::from_message(
+                "Interrupted because the app closed during the download",
+            ));
+        });
+        match updated {
+            Ok(Some(entry)) => emit_store_event(
+                app,
+                HISTORY_CHANGED_EVENT,
+                &HistoryChange::Updated {
+                    entry: Box::new(entry),
+                },
+            ),
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to update interrupted download {}: {}", entry.id, e),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn cancel_download(
+    download_id: String,
+    app: AppHandle,
+    manager: State<'_, DownloadManager>,
+) -> Result<bool, String> {
+    match manager.cancel(&download_id) {
+        Cancellation::Running => {
+            println!("Cancellation requested for ID: {}", download_id);
+            Ok(true)
+        }
+        Cancellation::Dequeued(job) => {
+            println!("Removed queued download: {}", download_id);
+            save_queue_to_store(&app, &manager.queued())?;
+
+            let settings = current_settings(&app).await?;
+            let cancelled = history_store(&app)
+                .update(&job.id, |entry| {
+                    entry.status = DownloadStatus::Cancelled;
+                    entry.record_finish();
+                })
+                .map_err(|e| format!("Failed to update history: {}", e))?;
+            if let Some(mut entry) = cancelled {
+                entry.hook_results = run_hooks(&settings.hooks, &entry).await;
+                webhooks::dispatch(&settings.webhooks, WebhookEvent::Cancelled, &entry, None);
+                save_download_to_history(&app, &entry).await?;
+            }
+            Ok(true)
+        }
+        Cancellation::NotFound => Ok(false),
+    }
+}
+
+#[tauri::command]
+pub async fn get_download_queue(
+    app: AppHandle,
+    manager: State<'_, DownloadManager>,
+) -> Result<QueueSnapshot, String> {
+    let settings = current_settings(&app).await?;
+    Ok(manager.snapshot(settings.max_concurrent_downloads))
+}
+
+fn backend_options(app: &AppHandle, settings: &AppSettings, output_dir: PathBuf) -> BackendOptions {
+    BackendOptions {
+        output_dir,
+        filename_options: FilenameOptions {
+            template: settings.filename_template.clone(),
+            ascii_only: settings.ascii_filenames,
+        },
+        staging_root: staging_root(settings),
+        disk_space: DiskSpaceOptions {
+            policy: settings.disk_space_policy,
+            min_free_bytes: settings.min_free_space_mb * 1024 * 1024,
+        },
+        progress_callback: None,
+        metadata_ttl: metadata_ttl(settings),
+        sites: settings.supported_sites.clone(),
+        binaries: app.state::<DownloadManager>().binaries(),
+        direct_downloads: settings.direct_downloads,
+    }
+}
+
+fn create_backend(settings: &AppSettings, options: BackendOptions) -> Result<Arc<dyn MediaBackend>, String> {
+    backend::create_backend(&settings.backend, options)
+        .map_err(|e| format!("Failed to initialize downloader: {}", e))
+}
+
+/// Tells every window that `STORE_FILE` changed so none of them has to poll.
+fn emit_store_event<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: &T) {
+    if let Err(e) = app.emit(event, payload.clone()) {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
+
+fn progress_emitter(app: &AppHandle) -> ProgressCallback {
+    let app = app.clone();
+    Arc::new(move |progress: DownloadProgress| {
+        if let Err(e) = app.emit("download-progress", &progress) {
+            eprintln!("Failed to emit download progress: {}", e);
+        }
+    })
+}
+
+// The download folder picked in the UI, the platform default and any extra
+// folders the user added in settings; requests may only write below these.
+fn approved_roots(settings: &AppSettings) -> Vec<PathBuf> {
+    let mut roots = vec![PathBuf::from(&settings.download_path)];
+    roots.extend(settings.approved_output_roots.iter().map(PathBuf::from));
+    if let Some(home) = dirs::home_dir() {
+        roots.push(home.join("Downloads"));
+    }
+    roots
+}
+
+fn metadata_ttl(settings: &AppSettings) -> Duration {
+    Duration::from_secs(settings.metadata_cache_ttl_minutes * 60)
+}
+
+fn staging_root(settings: &AppSettings) -> PathBuf {
+    settings
+        .staging_path
+        .as_deref()
+        .filter(|p| !p.is_empty())
+        .map(|p| staging::staging_root_in(Path::new(p)))
+        .unwrap_or_else(staging::default_staging_root)
+}
+
+pub async fn cleanup_staging_dirs(app: &AppHandle) {
+    let settings = match current_settings(app).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Failed to load settings for staging cleanup: {}", e);
+            AppSettings::default()
+        }
+    };
+
+    let root = staging_root(&settings);
+    let removed = staging::cleanup_staging_root(&root);
+    if removed > 0 {
+        println!("Removed {} leftover staging entries from {}", removed, root.display());
+    }
+}
+
+fn resolve_pipeline(
+    settings: &AppSettings,
+    requested: Option<&str>,
+) -> Result<Option<PostProcessPipeline>, String> {
+    let name = match requested.or(settings.default_pipeline.as_deref()) {
+        Some(name) if !name.is_empty() => name,
+        _ => return Ok(None),
+    };
+
+    settings
+        .pipelines
+        .iter()
+        .find(|p| p.name == name)
+        .cloned()
+        .map(Some)
+        .ok_or_else(|| format!("Unknown post-processing pipeline: {}", name))
+}
+
+async fn run_post_processing<F>(
+    backend: &dyn MediaBackend,
+    pipeline: &PostProcessPipeline,
+    collision_policy: CollisionPolicy,
+    history: &mut DownloadHistory,
+    on_progress: F,
+) where
+    F: Fn(PostProcessProgress),
+{
+    if !matches!(history.status, DownloadStatus::Completed) {
+        return;
+    }
+
+    let Some(file_path) = history.file_path.clone() else {
+        return;
+    };
+    if pipeline.steps.is_empty() {
+        return;
+    }
+
+    // A failed pipeline keeps the downloaded file, so the entry stays
+    // completed and carries the failure for the UI, hooks and webhooks.
+    let fail = |history: &mut DownloadHistory, message: String| {
+        eprintln!("Post-processing failed for ID {}, keeping original file: {}", history.id, message);
+        history.error = Some(DownloadFailure {
+            category: ErrorCategory::PostProcessing,
+            ..DownloadFailure::from_message(&message)
+        });
+    };
+
+    let ffmpeg_path = match backend.ffmpeg_path() {
+        Ok(path) => path,
+        Err(e) => {
+            on_progress(PostProcessProgress::failed(pipeline, &history.id, 0, e.to_string()));
+            fail(history, format!("Post-processing pipeline '{}' could not start: {}", pipeline.name, e));
+            return;
+        }
+    };
+
+    println!("Running post-processing pipeline '{}' for ID: {}", pipeline.name, history.id);
+    let processor = PostProcessor::new(ffmpeg_path);
+    let result = processor
+        .run(
+            pipeline,
+            &PathBuf::from(&file_path),
+            &history.id,
+            history.duration,
+            collision_policy,
+            on_progress,
+        )
+        .await;
+
+    match result {
+        Ok(path) => {
+            if let Some(ext) = path.extension() {
+                history.format = ext.to_string_lossy().to_string();
+            }
+            let new_path = path.to_string_lossy().to_string();
+            for output in history.output_files.iter_mut().filter(|p| **p == file_path) {
+                *output = new_path.clone();
+            }
+            history.file_path = Some(new_path);
+        }
+        Err(e) => fail(history, e.to_string()),
+    }
 }
 
 #[tauri::command]
 pub async fn get_download_history(app: AppHandle) -> Result<Vec<DownloadHistory>, String> {
-    load_history_from_store(&app).await
+    load_history(&app).await
+}
+
+#[tauri::command]
+pub async fn query_history(query: HistoryQuery, app: AppHandle) -> Result<HistoryPage, String> {
+    history_store(&app)
+        .query(&query)
+        .map_err(|e| format!("Failed to query history: {}", e))
+}
+
+/// What to do with a deleted entry's files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileRemoval {
+    #[default]
+    Keep,
+    Trash,
+    Delete,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteHistoryResult {
+    pub removed_entries: usize,
+    pub removed_files: usize,
+    /// Entries left alone because their download is still queued or running.
+    pub skipped_active: Vec<String>,
+    /// Files left in place because an entry that stays in history points at them.
+    pub kept_shared_files: Vec<String>,
+    pub file_errors: Vec<String>,
+}
+
+#[tauri::command]
+pub async fn delete_history_entries(
+    ids: Vec<String>,
+    files: Option<FileRemoval>,
+    app: AppHandle,
+    manager: State<'_, DownloadManager>,
+) -> Result<DeleteHistoryResult, String> {
+    let settings = current_settings(&app).await?;
+    let removal = files.unwrap_or_default();
+    let store = history_store(&app);
+    let mut result = DeleteHistoryResult::default();
+    let mut entries = Vec::new();
+
+    for id in ids {
+        let entry = store
+            .get(&id)
+            .map_err(|e| format!("Failed to load history: {}", e))?;
+        let Some(entry) = entry else {
+            continue;
+        };
+        if manager.has_job(&id) {
+            result.skipped_active.push(id);
+            continue;
+        }
+        entries.push(entry);
+    }
+    let deletable: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+
+    if removal != FileRemoval::Keep {
+        let deleting: HashSet<&str> = deletable.iter().map(String::as_str).collect();
+        // A skipped entry points at the file an earlier download produced.
+        for entry in entries.iter().filter(|entry| !matches!(entry.status, DownloadStatus::Skipped)) {
+            for path in entry_files(entry) {
+                if is_shared_file(&store, &path, &deleting)? {
+                    result.kept_shared_files.push(path.to_string_lossy().to_string());
+                    continue;
+                }
+                match remove_entry_file(&path, removal, &settings) {
+                    Ok(true) => result.removed_files += 1,
+                    Ok(false) => {}
+                    Err(e) => result.file_errors.push(format!("{}: {}", path.display(), e)),
+                }
+            }
+        }
+    }
+
+    result.removed_entries = store
+        .delete(&deletable)
+        .map_err(|e| format!("Failed to delete history entries: {}", e))?;
+    if result.removed_entries > 0 {
+        emit_store_event(&app, HISTORY_CHANGED_EVENT, &HistoryChange::Deleted { ids: deletable });
+    }
+    Ok(result)
+}
+
+fn entry_files(entry: &DownloadHistory) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = entry.output_files.iter().map(PathBuf::from).collect();
+    if let Some(path) = &entry.file_path {
+        let path = PathBuf::from(path);
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Whether an entry outside `deleting` records `path` as its file.
+fn is_shared_file(store: &HistoryStore, path: &Path, deleting: &HashSet<&str>) -> Result<bool, String> {
+    let entries = store
+        .find_by_file_path(&path.to_string_lossy())
+        .map_err(|e| format!("Failed to load history: {}", e))?;
+    Ok(entries.iter().any(|entry| !deleting.contains(entry.id.as_str())))
+}
+
+/// Removes one downloaded file. Files that are already gone count as done;
+/// paths outside the approved download roots are never touched.
+fn remove_entry_file(path: &Path, removal: FileRemoval, settings: &AppSettings) -> Result<bool, String> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let parent = path
+        .parent()
+        .ok_or_else(|| "Invalid file path".to_string())?
+        .to_string_lossy()
+        .to_string();
+    security::resolve_output_dir(&parent, &approved_roots(settings)).map_err(|e| e.to_string())?;
+
+    match removal {
+        FileRemoval::Keep => Ok(false),
+        FileRemoval::Trash => trash::move_to_trash(path).map(|_| true).map_err(|e| e.to_string()),
+        FileRemoval::Delete => std::fs::remove_file(path).map(|_| true).map_err(|e| e.to_string()),
+    }
+}
+
+/// Queues the entry's URL again, optionally with another format or quality,
+/// into the folder the original file was saved in.
+#[tauri::command]
+pub async fn redownload_entry(
+    id: String,
+    format: Option<String>,
+    quality: Option<String>,
+    app: AppHandle,
+    manager: State<'_, DownloadManager>,
+) -> Result<String, String> {
+    let entry = history_store(&app)
+        .get(&id)
+        .map_err(|e| format!("Failed to load history: {}", e))?
+        .ok_or_else(|| format!("History entry not found: {}", id))?;
+    let settings = current_settings(&app).await?;
+
+    let output_path = entry
+        .file_path
+        .as_deref()
+        .and_then(|path| Path::new(path).parent())
+        .filter(|dir| dir.is_dir())
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or(settings.download_path);
+
+    let request = DownloadRequest {
+        url: entry.url,
+        format: format.unwrap_or(entry.format),
+        quality: quality.unwrap_or(entry.quality),
+        output_path,
+        filename: None,
+        pipeline: None,
+        collision_policy: None,
+    };
+    queue_download(&app, &manager, request).await
+}
+
+fn existing_entry_file(app: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    let entry = history_store(app)
+        .get(id)
+        .map_err(|e| format!("Failed to load history: {}", e))?
+        .ok_or_else(|| format!("History entry not found: {}", id))?;
+    let path = entry
+        .file_path
+        .map(PathBuf::from)
+        .ok_or_else(|| "This download has no file".to_string())?;
+    if path.exists() {
+        Ok(path)
+    } else {
+        Err(format!("File no longer exists: {}", path.display()))
+    }
+}
+
+#[tauri::command]
+pub async fn open_history_file(id: String, app: AppHandle) -> Result<(), String> {
+    let path = existing_entry_file(&app, &id)?;
+    app.opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| format!("Failed to open file: {}", e))
+}
+
+#[tauri::command]
+pub async fn reveal_history_file(id: String, app: AppHandle) -> Result<(), String> {
+    let path = existing_entry_file(&app, &id)?;
+    app.opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| format!("Failed to reveal file: {}", e))
+}
+
+#[tauri::command]
+pub async fn verify_history(app: AppHandle) -> Result<VerifyReport, String> {
+    reconcile_history(&app).await
+}
+
+/// Background pass run at startup so the History tab reflects files that
+/// were deleted or moved while the app was closed.
+pub async fn verify_history_in_background(app: &AppHandle) {
+    match reconcile_history(app).await {
+        Ok(report) => println!(
+            "History verified: {} present, {} missing, {} relocated",
+            report.present, report.missing, report.relocated
+        ),
+        Err(e) => eprintln!("History verification failed: {}", e),
+    }
+}
+
+async fn reconcile_history(app: &AppHandle) -> Result<VerifyReport, String> {
+    let settings = current_settings(app).await?;
+    let entries = load_history(app).await?;
+    let roots = approved_roots(&settings);
+    let (report, changed) =
+        tokio::task::spawn_blocking(move || reconcile::verify_entries(entries, &roots))
+            .await
+            .map_err(|e| format!("History verification failed: {}", e))?;
+
+    let store = history_store(app);
+    for verified in changed {
+        // Only take over what verification owns; the rest of the entry may
+        // have been updated while the filesystem was scanned.
+        let updated = store
+            .update(&verified.id, |entry| {
+                if reconcile::is_verifiable(entry) {
+                    entry.status = verified.status.clone();
+                    entry.file_path = verified.file_path.clone();
+                    entry.output_files = verified.output_files.clone();
+                    entry.file_size = verified.file_size;
+                    entry.file_hash = verified.file_hash.clone();
+                }
+            })
+            .map_err(|e| format!("Failed to update history: {}", e))?;
+        if let Some(entry) = updated {
+            emit_store_event(
+                app,
+                HISTORY_CHANGED_EVENT,
+                &HistoryChange::Updated {
+                    entry: Box::new(entry),
+                },
+            );
+        }
+    }
+
+    Ok(report)
 }
 
 #[tauri::command]
@@ -110,20 +823,17 @@ pub async fn get_download_status(
     download_id: String,
     app: AppHandle,
 ) -> Result<Option<DownloadHistory>, String> {
-    let history = load_history_from_store(&app).await?;
-    Ok(history.into_iter().find(|d| d.id == download_id))
+    history_store(&app)
+        .get(&download_id)
+        .map_err(|e| format!("Failed to load history: {}", e))
 }
 
 #[tauri::command]
 pub async fn clear_download_history(app: AppHandle) -> Result<(), String> {
-    let store = app
-        .store(STORE_FILE)
-        .map_err(|e| format!("Failed to get store: {}", e))?;
-
-    store.delete(HISTORY_KEY);
-    store
-        .save()
-        .map_err(|e| format!("Failed to save store: {}", e))?;
+    history_store(&app)
+        .clear()
+        .map_err(|e| format!("Failed to clear history: {}", e))?;
+    emit_store_event(&app, HISTORY_CHANGED_EVENT, &HistoryChange::Cleared);
 
     Ok(())
 }
@@ -137,6 +847,23 @@ pub async fn get_default_download_path() -> Result<String, String> {
 
 
 
+#[tauri::command]
+pub async fn get_cache_info(app: AppHandle) -> Result<CacheInfo, String> {
+    let settings = current_settings(&app).await?;
+    Ok(cache::cache_info(&CacheDirs::new(&staging_root(&settings))))
+}
+
+#[tauri::command]
+pub async fn clear_cache(app: AppHandle) -> Result<CacheClearResult, String> {
+    let settings = current_settings(&app).await?;
+    let result = cache::clear_cache(&CacheDirs::new(&staging_root(&settings)));
+    println!(
+        "Cache cleared: {} entries, {} bytes reclaimed",
+        result.removed_entries, result.reclaimed_bytes
+    );
+    Ok(result)
+}
+
 #[tauri::command]
 pub async fn check_yt_dlp_installed() -> Result<bool, String> {
     match std::process::Command::new("yt-dlp")
@@ -170,8 +897,12 @@ pub async fn get_supported_qualities() -> Result<Vec<String>, String> {
 }
 
 #[tauri::command]
-pub async fn check_dependencies() -> Result<bool, String> {
-    match YouTubeDownloader::check_dependencies().await {
+pub async fn check_dependencies(app: AppHandle) -> Result<bool, String> {
+    let settings = current_settings(&app).await?;
+    let output_dir = get_default_download_path().await?;
+    let backend = create_backend(&settings, backend_options(&app, &settings, PathBuf::from(output_dir)))?;
+
+    match backend.check_dependencies().await {
         Ok(_) => Ok(true),
         Err(e) => {
             eprintln!("Dependencies check failed: {}", e);
@@ -231,91 +962,168 @@ pub async fn get_debug_info() -> Result<serde_json::Value, String> {
 }
 
 #[tauri::command]
-pub async fn get_download_statistics(app: AppHandle) -> Result<DownloadStats, String> {
-    let history = load_history_from_store(&app).await?;
+pub async fn get_download_statistics(
+    query: Option<StatsQuery>,
+    app: AppHandle,
+) -> Result<DownloadStats, String> {
+    history_store(&app)
+        .statistics(&query.unwrap_or_default())
+        .map_err(|e| format!("Failed to compute statistics: {}", e))
+}
 
-    let total = history.len();
-    let completed = history
-        .iter()
-        .filter(|d| matches!(d.status, DownloadStatus::Completed))
-        .count();
-    let failed = history
-        .iter()
-        .filter(|d| matches!(d.status, DownloadStatus::Failed))
-        .count();
-    let downloading = history
-        .iter()
-        .filter(|d| matches!(d.status, DownloadStatus::Downloading))
-        .count();
+/// The statistics time series for `query` as CSV text, for the frontend to
+/// save where the user chooses.
+#[tauri::command]
+pub async fn export_download_statistics_csv(
+    query: Option<StatsQuery>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let stats = get_download_statistics(query, app).await?;
+    Ok(stats::series_csv(&stats))
+}
 
-    let formats: HashMap<String, usize> = history.iter().fold(HashMap::new(), |mut acc, d| {
-        *acc.entry(d.format.clone()).or_insert(0) += 1;
-        acc
-    });
+fn history_store(app: &AppHandle) -> State<'_, HistoryStore> {
+    app.state::<HistoryStore>()
+}
 
-    Ok(DownloadStats {
-        total,
-        completed,
-        failed,
-        downloading,
-        most_used_format: formats
-            .iter()
-            .max_by_key(|(_, &count)| count)
-            .map(|(format, _)| format.clone()),
-        formats_breakdown: formats,
-    })
+async fn load_history(app: &AppHandle) -> Result<Vec<DownloadHistory>, String> {
+    history_store(app)
+        .list()
+        .map_err(|e| format!("Failed to load history: {}", e))
 }
 
-async fn load_history_from_store(app: &AppHandle) -> Result<Vec<DownloadHistory>, String> {
-    let store = app
-        .store(STORE_FILE)
-        .map_err(|e| format!("Failed to get store: {}", e))?;
+/// Moves entries from the `downloads` key of `STORE_FILE`, where history was
+/// kept before the database, into the history database.
+pub async fn migrate_legacy_history(app: &AppHandle) {
+    let store = match app.store(STORE_FILE) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Failed to open store for history migration: {}", e);
+            return;
+        }
+    };
+    let Some(value) = store.get(HISTORY_KEY) else {
+        return;
+    };
 
-    match store.get(HISTORY_KEY) {
-        Some(value) => serde_json::from_value::<Vec<DownloadHistory>>(value.clone())
-            .map_err(|e| format!("Failed to deserialize history: {}", e)),
-        None => Ok(Vec::new()),
+    let entries: Vec<DownloadHistory> = match serde_json::from_value(value) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read legacy history, leaving it in place: {}", e);
+            return;
+        }
+    };
+
+    match history_store(app).import(&entries) {
+        Ok(imported) => {
+            println!("Migrated {} history entries into the history database", imported);
+            store.delete(HISTORY_KEY);
+            if let Err(e) = store.save() {
+                eprintln!("Failed to save store after history migration: {}", e);
+            }
+            emit_store_event(app, HISTORY_CHANGED_EVENT, &HistoryChange::Imported { count: imported });
+        }
+        Err(e) => eprintln!("Failed to migrate legacy history: {}", e),
     }
 }
 
-async fn save_download_to_store(app: &AppHandle, download: &DownloadHistory) -> Result<(), String> {
+async fn load_settings_from_store(app: &AppHandle) -> Result<AppSettings, String> {
     let store = app
         .store(STORE_FILE)
         .map_err(|e| format!("Failed to get store: {}", e))?;
 
-    let mut history = load_history_from_store(app).await?;
+    match store.get(SETTINGS_KEY) {
+        Some(value) => serde_json::from_value::<AppSettings>(value.clone())
+            .map_err(|e| format!("Failed to deserialize settings: {}", e)),
+        None => Ok(AppSettings::default()),
+    }
+}
 
-    if let Some(index) = history.iter().position(|d| d.id == download.id) {
-        history[index] = download.clone();
-    } else {
-        history.push(download.clone());
+/// Settings cached in the `DownloadManager`, read from the store on first use.
+async fn current_settings(app: &AppHandle) -> Result<AppSettings, String> {
+    let manager = app.state::<DownloadManager>();
+    if let Some(settings) = manager.settings() {
+        return Ok(settings);
     }
 
-    history.sort_by(|a, b| b.downloaded_at.cmp(&a.downloaded_at));
+    let settings = load_settings_from_store(app).await?;
+    manager.update_settings(settings.clone());
+    Ok(settings)
+}
 
-    if history.len() > 100 {
-        history.truncate(100);
+fn load_queue_from_store(app: &AppHandle) -> Result<Vec<QueuedDownload>, String> {
+    let store = app
+        .store(STORE_FILE)
+        .map_err(|e| format!("Failed to get store: {}", e))?;
+
+    match store.get(QUEUE_KEY) {
+        Some(value) => serde_json::from_value::<Vec<QueuedDownload>>(value.clone())
+            .map_err(|e| format!("Failed to deserialize queue: {}", e)),
+        None => Ok(Vec::new()),
     }
+}
 
-    let history_value = serde_json::to_value(&history)
-        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+fn save_queue_to_store(app: &AppHandle, queue: &[QueuedDownload]) -> Result<(), String> {
+    let store = app
+        .store(STORE_FILE)
+        .map_err(|e| format!("Failed to get store: {}", e))?;
 
-    store.set(HISTORY_KEY, history_value);
+    let queue_value = serde_json::to_value(queue)
+        .map_err(|e| format!("Failed to serialize queue: {}", e))?;
+    store.set(QUEUE_KEY, queue_value);
     store
         .save()
         .map_err(|e| format!("Failed to save store: {}", e))?;
 
+    // Running jobs are not persisted, but listeners want the whole picture.
+    let manager = app.state::<DownloadManager>();
+    let max_concurrent = manager
+        .settings()
+        .map(|settings| settings.max_concurrent_downloads)
+        .unwrap_or_else(default_max_concurrent_downloads);
+    emit_store_event(app, QUEUE_CHANGED_EVENT, &manager.snapshot(max_concurrent));
+
     Ok(())
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct DownloadStats {
-    pub total: usize,
-    pub completed: usize,
-    pub failed: usize,
-    pub downloading: usize,
-    pub most_used_format: Option<String>,
-    pub formats_breakdown: HashMap<String, usize>,
+async fn save_download_to_history(app: &AppHandle, download: &DownloadHistory) -> Result<(), String> {
+    let history = history_store(app);
+    history
+        .upsert(download)
+        .map_err(|e| format!("Failed to save history: {}", e))?;
+    emit_store_event(
+        app,
+        HISTORY_CHANGED_EVENT,
+        &HistoryChange::Updated {
+            entry: Box::new(download.clone()),
+        },
+    );
+
+    let settings = current_settings(app).await?;
+    apply_history_retention(app, settings.history_retention);
+    Ok(())
+}
+
+fn apply_history_retention(app: &AppHandle, policy: RetentionPolicy) {
+    match history_store(app).apply_retention(policy) {
+        Ok(0) => {}
+        Ok(removed) => {
+            println!("History retention removed {} entries", removed);
+            emit_store_event(app, HISTORY_CHANGED_EVENT, &HistoryChange::Pruned { removed });
+        }
+        Err(e) => eprintln!("Failed to apply history retention: {}", e),
+    }
+}
+
+/// Payload of the `history-changed` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HistoryChange {
+    Updated { entry: Box<DownloadHistory> },
+    Cleared,
+    Imported { count: usize },
+    Deleted { ids: Vec<String> },
+    Pruned { removed: usize },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -323,6 +1131,58 @@ pub struct AppSettings {
     pub download_path: String,
     pub default_format: String,
     pub default_quality: String,
+    #[serde(default)]
+    pub pipelines: Vec<PostProcessPipeline>,
+    #[serde(default)]
+    pub default_pipeline: Option<String>,
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+    #[serde(default)]
+    pub ascii_filenames: bool,
+    #[serde(default)]
+    pub collision_policy: CollisionPolicy,
+    #[serde(default)]
+    pub staging_path: Option<String>,
+    #[serde(default)]
+    pub disk_space_policy: DiskSpacePolicy,
+    #[serde(default = "default_min_free_space_mb")]
+    pub min_free_space_mb: u64,
+    #[serde(default = "default_metadata_cache_ttl_minutes")]
+    pub metadata_cache_ttl_minutes: u64,
+    #[serde(default)]
+    pub approved_output_roots: Vec<String>,
+    #[serde(default = "sites::default_sites")]
+    pub supported_sites: Vec<SiteConfig>,
+    #[serde(default)]
+    pub backend: BackendKind,
+    #[serde(default = "default_max_concurrent_downloads")]
+    pub max_concurrent_downloads: usize,
+    #[serde(default)]
+    pub history_retention: RetentionPolicy,
+    /// Lets links to plain media files on public hosts bypass the supported
+    /// sites list and download without yt-dlp.
+    #[serde(default)]
+    pub direct_downloads: bool,
+}
+
+fn default_max_concurrent_downloads() -> usize {
+    2
+}
+
+fn default_metadata_cache_ttl_minutes() -> u64 {
+    60
+}
+
+fn default_min_free_space_mb() -> u64 {
+    512
+}
+
+fn default_filename_template() -> String {
+    DEFAULT_FILENAME_TEMPLATE.to_string()
 }
 
 impl Default for AppSettings {
@@ -334,6 +1194,23 @@ impl Default for AppSettings {
             download_path: downloads_dir.to_string_lossy().to_string(),
             default_format: "mp4".to_string(),
             default_quality: "high".to_string(),
+            pipelines: Vec::new(),
+            default_pipeline: None,
+            hooks: Vec::new(),
+            webhooks: Vec::new(),
+            filename_template: default_filename_template(),
+            ascii_filenames: false,
+            collision_policy: CollisionPolicy::default(),
+            staging_path: None,
+            disk_space_policy: DiskSpacePolicy::default(),
+            min_free_space_mb: default_min_free_space_mb(),
+            metadata_cache_ttl_minutes: default_metadata_cache_ttl_minutes(),
+            approved_output_roots: Vec::new(),
+            supported_sites: sites::default_sites(),
+            backend: BackendKind::default(),
+            max_concurrent_downloads: default_max_concurrent_downloads(),
+            history_retention: RetentionPolicy::default(),
+            direct_downloads: false,
         }
     }
 }
@@ -350,6 +1227,9 @@ pub async fn save_settings(settings: AppSettings, app: AppHandle) -> Result<(),
     store
         .save()
         .map_err(|e| format!("Failed to save settings: {}", e))?;
+    app.state::<DownloadManager>().update_settings(settings.clone());
+    emit_store_event(&app, SETTINGS_CHANGED_EVENT, &settings);
+    apply_history_retention(&app, settings.history_retention);
 
     println!("Settings saved: {:?}", settings);
     Ok(())
@@ -366,6 +1246,7 @@ pub async fn load_settings(app: AppHandle) -> Result<AppSettings, String> {
             let settings: AppSettings = serde_json::from_value(value.clone())
                 .map_err(|e| format!("Failed to deserialize settings: {}", e))?;
             println!("Settings loaded: {:?}", settings);
+            app.state::<DownloadManager>().update_settings(settings.clone());
             Ok(settings)
         }
         None => {
@@ -0,0 +1,271 @@
+use crate::backend::{BackendOptions, Binaries, CancellationToken, FormatInfo, MediaBackend};
+use crate::cache;
+use crate::filename::{self, FilenameContext};
+use crate::postprocess::{PostProcessPipeline, PostProcessStep, PostProcessor};
+use crate::sites::{self, SiteConfig};
+use crate::staging;
+use crate::urls;
+use crate::youtube::{
+    CollisionPolicy, CollisionResolution, DownloadError, DownloadFailure, DownloadHistory,
+    DownloadProgress, DownloadRequest, DownloadStatus, VideoInfo, YouTubeDownloader,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use yt_dlp::fetcher::deps::Libraries;
+use yt_dlp::model::format_selector::{
+    AudioCodecPreference, AudioQuality, VideoCodecPreference, VideoQuality,
+};
+use yt_dlp::Youtube;
+
+/// Backend built on the `yt-dlp` crate. It only understands YouTube and the
+/// crate gives no byte-level progress, so progress is reported per stage and
+/// cancellation drops the in-flight transfer.
+pub struct CrateBackend {
+    options: BackendOptions,
+    libraries: Libraries,
+    yt_dlp_version: Option<String>,
+}
+
+impl CrateBackend {
+    pub fn new(options: BackendOptions) -> Result<Self> {
+        let binaries = match &options.binaries {
+            Some(binaries) => binaries.clone(),
+            None => Binaries::resolve()?,
+        };
+        let libraries = Libraries::new(binaries.yt_dlp, binaries.ffmpeg);
+        Ok(Self {
+            options,
+            libraries,
+            yt_dlp_version: binaries.yt_dlp_version,
+        })
+    }
+
+    fn fetcher(&self, output_dir: &Path) -> Result<Youtube> {
+        Youtube::new(self.libraries.clone(), output_dir)
+            .map_err(|e| anyhow!("Failed to initialize yt-dlp crate: {}", e))
+    }
+
+    async fn video_data(&self, url: &str) -> Result<Value> {
+        let fetcher = self.fetcher(&cache::cache_root().join("yt-dlp-crate"))?;
+        let video = fetcher
+            .fetch_video_infos(url.to_string())
+            .await
+            .map_err(|e| anyhow!("Failed to get video info: {}", e))?;
+        Ok(serde_json::to_value(&video)?)
+    }
+
+    fn report(&self, download_id: &str, status: DownloadStatus, progress: f64) {
+        if let Some(callback) = &self.options.progress_callback {
+            callback(DownloadProgress::new(download_id, status, progress));
+        }
+    }
+
+    async fn fetch_media(&self, request: &DownloadRequest, staging_dir: &Path, stem: &str) -> Result<PathBuf> {
+        let fetcher = self.fetcher(staging_dir)?;
+        let url = request.url.clone();
+
+        match request.format.as_str() {
+            "mp4" | "webm" => {
+                let (video_codec, audio_codec) = if request.format == "mp4" {
+                    (VideoCodecPreference::AVC1, AudioCodecPreference::AAC)
+                } else {
+                    (VideoCodecPreference::VP9, AudioCodecPreference::Opus)
+                };
+                fetcher
+                    .download_video_with_quality(
+                        url,
+                        format!("{}.{}", stem, request.format),
+                        video_quality(&request.quality),
+                        video_codec,
+                        audio_quality(&request.quality),
+                        audio_codec,
+                    )
+                    .await
+                    .map_err(|e| anyhow!("Download failed: {}", e))
+            }
+            "mp3" | "wav" => {
+                let source = fetcher
+                    .download_audio_stream_with_quality(
+                        url,
+                        format!("{}.source", stem),
+                        audio_quality(&request.quality),
+                        AudioCodecPreference::Any,
+                    )
+                    .await
+                    .map_err(|e| anyhow!("Download failed: {}", e))?;
+
+                let pipeline = PostProcessPipeline {
+                    name: "convert".to_string(),
+                    steps: vec![PostProcessStep::Convert {
+                        format: request.format.clone(),
+                    }],
+                };
+                PostProcessor::new(self.libraries.ffmpeg.clone())
+                    .run(&pipeline, &source, "", None, CollisionPolicy::Overwrite, |_| {})
+                    .await
+            }
+            format => Err(anyhow!("Unsupported format: {}", format)),
+        }
+    }
+}
+
+fn video_quality(quality: &str) -> VideoQuality {
+    match quality {
+        "best" => VideoQuality::Best,
+        "medium" => VideoQuality::Medium,
+        "low" => VideoQuality::Low,
+        "worst" => VideoQuality::Worst,
+        _ => VideoQuality::High,
+    }
+}
+
+fn audio_quality(quality: &str) -> AudioQuality {
+    match quality {
+        "best" => AudioQuality::Best,
+        "medium" => AudioQuality::Medium,
+        "low" => AudioQuality::Low,
+        "worst" => AudioQuality::Worst,
+        _ => AudioQuality::High,
+    }
+}
+
+#[async_trait]
+impl MediaBackend for CrateBackend {
+    fn name(&self) -> &'static str {
+        "yt-dlp-crate"
+    }
+
+    async fn check_dependencies(&self) -> Result<()> {
+        YouTubeDownloader::check_dependencies().await
+    }
+
+    fn ffmpeg_path(&self) -> Result<PathBuf> {
+        Ok(self.libraries.ffmpeg.clone())
+    }
+
+    async fn probe(&self, url: &str, _refresh: bool) -> Result<VideoInfo> {
+        let video_data = self.video_data(url).await?;
+        Ok(YouTubeDownloader::parse_video_info(url, &video_data))
+    }
+
+    async fn list_formats(&self, url: &str) -> Result<Vec<FormatInfo>> {
+        let video_data = self.video_data(url).await?;
+        Ok(FormatInfo::list_from_video_data(&video_data))
+    }
+
+    async fn identify_site(&self, url: &str, _extract: bool) -> Result<SiteConfig> {
+        if !urls::is_supported_url(url) {
+            return Err(anyhow!("The yt-dlp crate backend only supports YouTube URLs"));
+        }
+        match sites::find_site(&self.options.sites, sites::YOUTUBE_EXTRACTOR) {
+            Some(site) if site.enabled => Ok(site.clone()),
+            _ => Err(anyhow!("Downloads from YouTube are disabled in settings")),
+        }
+    }
+
+    async fn download(
+        &self,
+        request: DownloadRequest,
+        download_id: String,
+        cancel: CancellationToken,
+    ) -> Result<DownloadHistory> {
+        self.identify_site(&request.url, false).await?;
+        let video_info = self.probe(&request.url, false).await?;
+
+        let filename = match &request.filename {
+            Some(filename) => PathBuf::from(filename::sanitize_file_name(
+                filename,
+                self.options.filename_options.ascii_only,
+            )),
+            None => filename::render_filename(
+                &self.options.filename_options,
+                &FilenameContext {
+                    title: video_info.title.clone(),
+                    uploader: video_info.uploader.clone(),
+                    upload_date: video_info.upload_date.clone(),
+                    id: video_info.id.clone(),
+                    quality: request.quality.clone(),
+                    ext: request.format.clone(),
+                    ..Default::default()
+                },
+            ),
+        };
+        let policy = request.collision_policy.unwrap_or_default();
+        let filename = match YouTubeDownloader::resolve_collision_in(
+            &self.options.output_dir,
+            &filename.to_string_lossy(),
+            &request.format,
+            policy,
+        )? {
+            CollisionResolution::Proceed(filename) => PathBuf::from(filename),
+            CollisionResolution::Existing(existing) => {
+                let mut history = YouTubeDownloader::skipped_history(
+                    download_id,
+                    video_info.title.clone(),
+                    request,
+                    &existing,
+                    sites::YOUTUBE_EXTRACTOR.to_string(),
+                );
+                history.apply_video_info(&video_info);
+                return Ok(history);
+            }
+        };
+        let stem = filename::strip_media_extension(&filename)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "download".to_string());
+        let relative_dir = filename.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let staging_dir = staging::create_staging_dir(&self.options.staging_root, &download_id)?;
+        self.report(&download_id, DownloadStatus::Downloading, 0.0);
+
+        let result = tokio::select! {
+            result = self.fetch_media(&request, &staging_dir, &stem) => result,
+            _ = cancel.cancelled() => Err(DownloadError::Cancelled.into()),
+        };
+        let result = result.and_then(|staged| {
+            let file_name = staged
+                .file_name()
+                .ok_or_else(|| anyhow!("Invalid output file: {}", staged.display()))?;
+            let destination = self.options.output_dir.join(&relative_dir).join(file_name);
+            staging::move_file(&staged, &destination, policy == CollisionPolicy::Overwrite)
+                .map_err(|e| staging::keep_staging_dir(&staging_dir, e))?;
+            Ok(destination)
+        });
+        staging::remove_staging_dir(&staging_dir);
+
+        let (status, error) = match &result {
+            Ok(_) => (DownloadStatus::Completed, None),
+            Err(e) if DownloadError::is_cancellation(e) => (DownloadStatus::Cancelled, None),
+            Err(e) => {
+                eprintln!("yt-dlp crate download failed: {}", e);
+                (DownloadStatus::Failed, Some(DownloadFailure::from_error(e)))
+            }
+        };
+        if matches!(status, DownloadStatus::Completed) {
+            self.report(&download_id, DownloadStatus::Completed, 100.0);
+        }
+        let file_path = result.ok().map(|p| p.to_string_lossy().to_string());
+
+        let mut history = DownloadHistory {
+            id: download_id,
+            url: request.url,
+            status,
+            downloaded_at: Utc::now(),
+            output_files: file_path.iter().cloned().collect(),
+            file_path,
+            format: request.format,
+            quality: request.quality,
+            hook_results: Vec::new(),
+            extractor: Some(sites::YOUTUBE_EXTRACTOR.to_string()),
+            error,
+            yt_dlp_version: self.yt_dlp_version.clone(),
+            ..Default::default()
+        };
+        history.apply_video_info(&video_info);
+        Ok(history)
+    }
+}
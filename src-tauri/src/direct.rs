@@ -0,0 +1,546 @@
+use crate::backend::CancellationToken;
+use crate::filename;
+use crate::staging;
+use crate::youtube::{DownloadError, DownloadProgress, DownloadStatus, ProgressCallback};
+use anyhow::{anyhow, Result};
+use reqwest::header::{
+    ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE,
+    LAST_MODIFIED, RANGE,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+pub const DIRECT_EXTRACTOR: &str = "Direct";
+
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mov", "webm", "mkv", "avi", "mp3", "m4a", "aac", "ogg", "oga", "opus", "wav",
+    "flac",
+];
+const MAX_ATTEMPTS: u32 = 5;
+const MAX_REDIRECTS: usize = 10;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteMedia {
+    pub url: String,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    pub accepts_ranges: bool,
+    pub file_name: Option<String>,
+    pub extension: String,
+    /// Strong ETag or Last-Modified date, sent as `If-Range` when resuming so
+    /// a file that changed on the server is fetched again from the start.
+    #[serde(default)]
+    pub validator: Option<String>,
+}
+
+impl RemoteMedia {
+    pub fn title(&self) -> String {
+        self.file_name
+            .as_deref()
+            .map(|name| filename::strip_media_extension(Path::new(name)).to_string_lossy().to_string())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| "download".to_string())
+    }
+}
+
+enum TransferError {
+    Retryable(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+impl From<reqwest::Error> for TransferError {
+    fn from(e: reqwest::Error) -> Self {
+        TransferError::Retryable(e.into())
+    }
+}
+
+impl From<std::io::Error> for TransferError {
+    fn from(e: std::io::Error) -> Self {
+        TransferError::Fatal(e.into())
+    }
+}
+
+pub fn is_media_content_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    essence.starts_with("video/") || essence.starts_with("audio/")
+}
+
+fn extension_for_content_type(content_type: &str) -> Option<&'static str> {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    match essence.as_str() {
+        "video/mp4" => Some("mp4"),
+        "video/webm" | "audio/webm" => Some("webm"),
+        "video/quicktime" => Some("mov"),
+        "video/x-matroska" => Some("mkv"),
+        "video/x-msvideo" => Some("avi"),
+        "audio/mpeg" | "audio/mp3" => Some("mp3"),
+        "audio/mp4" | "audio/x-m4a" => Some("m4a"),
+        "audio/aac" => Some("aac"),
+        "audio/ogg" => Some("ogg"),
+        "audio/opus" => Some("opus"),
+        "audio/wav" | "audio/x-wav" | "audio/wave" => Some("wav"),
+        "audio/flac" | "audio/x-flac" => Some("flac"),
+        _ => None,
+    }
+}
+
+/// The file name from a Content-Disposition header, preferring the RFC 5987
+/// `filename*` parameter over the plain `filename` one.
+fn file_name_from_disposition(value: &str) -> Option<String> {
+    let parameter = |name: &str| {
+        value.split(';').find_map(|part| {
+            let (key, value) = part.split_once('=')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    };
+    parameter("filename*")
+        .and_then(decode_extended_value)
+        .or_else(|| parameter("filename").map(|name| name.trim_matches('"').to_string()))
+        .filter(|name| !name.is_empty())
+}
+
+/// Decodes an RFC 5987 `charset'language'percent-encoded` value.
+fn decode_extended_value(value: &str) -> Option<String> {
+    let mut parts = value.trim_matches('"').splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?.as_bytes();
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+    while index < encoded.len() {
+        if encoded[index] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(index + 1..index + 3)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            bytes.push(encoded[index]);
+            index += 1;
+        }
+    }
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+fn file_name_from_url(url: &reqwest::Url) -> Option<String> {
+    url.path_segments()?
+        .rev()
+        .find(|segment| !segment.is_empty())
+        .map(|segment| segment.to_string())
+}
+
+pub fn is_media_extension(extension: &str) -> bool {
+    MEDIA_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+}
+
+fn media_extension(file_name: Option<&str>) -> Option<String> {
+    let extension = Path::new(file_name?).extension()?.to_string_lossy().to_ascii_lowercase();
+    is_media_extension(&extension).then_some(extension)
+}
+
+fn claimed_partials() -> &'static Mutex<HashSet<PathBuf>> {
+    static CLAIMED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    CLAIMED.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Partial file named after its URL, so a retry of the same link under any
+/// download id resumes where the last attempt ended. Only one download at a
+/// time may hold it.
+pub struct PartialFile {
+    path: PathBuf,
+}
+
+impl PartialFile {
+    pub fn claim(dir: &Path, url: &str) -> Option<Self> {
+        let path = dir.join(format!("{}.part", hex::encode(Sha256::digest(url.as_bytes()))));
+        let mut claimed = claimed_partials().lock().ok()?;
+        claimed.insert(path.clone()).then_some(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Ok(mut claimed) = claimed_partials().lock() {
+            claimed.remove(&self.path);
+        }
+    }
+}
+
+/// Whether `ip` is on the public internet rather than this machine, a private
+/// network or a reserved range.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_ip(IpAddr::V4(v4)),
+            None => {
+                let first = v6.segments()[0];
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Rejects URLs that are not http(s) or that name a non-public IP directly.
+/// Host names are checked when they are resolved, by `PublicResolver`.
+fn check_public_url(url: &reqwest::Url) -> Result<()> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!("Only http and https links can be downloaded directly"));
+    }
+    let ip = match url.host() {
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+        Some(url::Host::Domain(_)) => return Ok(()),
+        None => return Err(anyhow!("{} has no host", url)),
+    };
+    if is_public_ip(ip) {
+        Ok(())
+    } else {
+        Err(anyhow!("{} is not a public address", ip))
+    }
+}
+
+/// Resolves host names to public addresses only, so neither a link nor a
+/// redirect can point direct downloads at this machine or the local network.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| is_public_ip(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(anyhow!("{} does not resolve to a public address", host).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+fn format_rate(bytes_per_second: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes_per_second;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2}{}/s", value, UNITS[unit])
+}
+
+fn format_eta(seconds: u64) -> String {
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+pub struct DirectDownloader {
+    client: reqwest::Client,
+    progress_callback: Option<ProgressCallback>,
+    public_only: bool,
+}
+
+impl DirectDownloader {
+    /// A downloader that only talks to public http(s) hosts.
+    pub fn new() -> Result<Self> {
+        let redirects = reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error(anyhow!("Too many redirects"))
+            } else if let Err(e) = check_public_url(attempt.url()) {
+                attempt.error(e)
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .redirect(redirects)
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()?;
+        Ok(Self {
+            client,
+            progress_callback: None,
+            public_only: true,
+        })
+    }
+
+    /// A downloader that may also reach this machine, for tests against a
+    /// local server.
+    #[cfg(test)]
+    pub(crate) fn allowing_local_hosts() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            progress_callback: None,
+            public_only: false,
+        })
+    }
+
+    pub fn with_progress_callback(mut self, callback: Option<ProgressCallback>) -> Self {
+        self.progress_callback = callback;
+        self
+    }
+
+    fn report_progress(&self, progress: DownloadProgress) {
+        if let Some(callback) = &self.progress_callback {
+            callback(progress);
+        }
+    }
+
+    /// Returns the media description when `url` serves an audio or video file
+    /// directly, or `None` for pages that need an extractor.
+    pub async fn probe(&self, url: &str) -> Result<Option<RemoteMedia>> {
+        let parsed = reqwest::Url::parse(url)?;
+        if self.public_only {
+            check_public_url(&parsed)?;
+        }
+        let mut response = self.client.head(parsed.clone()).send().await?;
+        if response.status() == StatusCode::METHOD_NOT_ALLOWED || response.status() == StatusCode::NOT_IMPLEMENTED {
+            response = self.client.get(parsed).header(RANGE, "bytes=0-0").send().await?;
+        }
+        if !response.status().is_success() {
+            return Err(anyhow!("Server returned {} for {}", response.status(), url));
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let content_type = header(CONTENT_TYPE);
+        let file_name = header(CONTENT_DISPOSITION)
+            .and_then(|v| file_name_from_disposition(&v))
+            .or_else(|| file_name_from_url(response.url()));
+
+        let extension = media_extension(file_name.as_deref())
+            .or_else(|| content_type.as_deref().and_then(extension_for_content_type).map(|e| e.to_string()));
+        let is_media = match content_type.as_deref() {
+            Some(ct) if is_media_content_type(ct) => true,
+            // Plenty of CDNs serve media as a generic binary blob.
+            Some(ct) if ct.starts_with("application/octet-stream") => {
+                media_extension(file_name.as_deref()).is_some()
+            }
+            _ => false,
+        };
+        if !is_media {
+            return Ok(None);
+        }
+
+        let content_length = if response.status() == StatusCode::PARTIAL_CONTENT {
+            header(CONTENT_RANGE)
+                .and_then(|v| v.rsplit('/').next().and_then(|total| total.parse().ok()))
+        } else {
+            header(CONTENT_LENGTH).and_then(|v| v.parse().ok())
+        };
+        let accepts_ranges = response.status() == StatusCode::PARTIAL_CONTENT
+            || header(ACCEPT_RANGES).map(|v| v.contains("bytes")).unwrap_or(false);
+        // Weak ETags may not be used with If-Range.
+        let validator = header(ETAG)
+            .filter(|etag| !etag.starts_with("W/"))
+            .or_else(|| header(LAST_MODIFIED));
+
+        Ok(Some(RemoteMedia {
+            url: response.url().to_string(),
+            content_type,
+            content_length,
+            accepts_ranges,
+            file_name,
+            extension: extension.unwrap_or_else(|| "bin".to_string()),
+            validator,
+        }))
+    }
+
+    /// Downloads `media` into `destination` through `partial`, resuming with
+    /// Range requests after interruptions and verifying the final size. The
+    /// partial file stays behind when the download fails or is cancelled, so
+    /// a later attempt can pick it up.
+    pub async fn download(
+        &self,
+        media: &RemoteMedia,
+        partial: &Path,
+        destination: &Path,
+        download_id: &str,
+        cancel: &CancellationToken,
+    ) -> Result<u64> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.transfer(media, partial, download_id, cancel).await {
+                Ok(()) => break,
+                Err(TransferError::Retryable(e)) if attempt < MAX_ATTEMPTS && !cancel.is_cancelled() => {
+                    eprintln!(
+                        "Direct download interrupted (attempt {}/{}): {}, resuming",
+                        attempt, MAX_ATTEMPTS, e
+                    );
+                    tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
+                }
+                Err(TransferError::Retryable(e)) | Err(TransferError::Fatal(e)) => return Err(e),
+            }
+        }
+
+        let size = std::fs::metadata(partial)?.len();
+        if let Some(expected) = media.content_length {
+            if size != expected {
+                let _ = std::fs::remove_file(partial);
+                return Err(anyhow!(
+                    "Downloaded size {} does not match Content-Length {}",
+                    size,
+                    expected
+                ));
+            }
+        }
+        staging::move_file(partial, destination, true)?;
+
+        let mut progress = DownloadProgress::new(download_id, DownloadStatus::Downloading, 100.0);
+        progress.filename = Some(destination.to_string_lossy().to_string());
+        self.report_progress(progress);
+        Ok(size)
+    }
+
+    async fn transfer(
+        &self,
+        media: &RemoteMedia,
+        partial: &Path,
+        download_id: &str,
+        cancel: &CancellationToken,
+    ) -> Result<(), TransferError> {
+        let mut offset = std::fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+        if let Some(total) = media.content_length {
+            if offset == total {
+                return Ok(());
+            }
+            if offset > total {
+                offset = 0;
+            }
+        }
+
+        let mut request = self.client.get(&media.url);
+        if offset > 0 && media.accepts_ranges {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+            if let Some(validator) = &media.validator {
+                request = request.header(IF_RANGE, validator);
+            }
+        }
+        let mut response = request.send().await?;
+
+        let status = response.status();
+        let resumed = status == StatusCode::PARTIAL_CONTENT
+            && response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("bytes "))
+                .and_then(|v| v.split('-').next())
+                .and_then(|start| start.parse::<u64>().ok())
+                == Some(offset);
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(TransferError::Retryable(anyhow!("Server returned {}", status)));
+        }
+        if !status.is_success() {
+            return Err(TransferError::Fatal(anyhow!("Server returned {}", status)));
+        }
+        if !resumed {
+            // The server ignored the range (or there was nothing to resume), so
+            // start the file over rather than appending a second copy.
+            offset = 0;
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(partial)
+            .await?;
+
+        let total = media.content_length;
+        let started = Instant::now();
+        let mut last_report = Instant::now();
+        let mut downloaded = offset;
+        let mut transferred = 0u64;
+
+        loop {
+            let chunk = tokio::select! {
+                chunk = response.chunk() => chunk?,
+                _ = cancel.cancelled() => {
+                    return Err(TransferError::Fatal(DownloadError::Cancelled.into()));
+                }
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            file.write_all(&chunk).await?;
+            downloaded += chunk.len() as u64;
+            transferred += chunk.len() as u64;
+
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                last_report = Instant::now();
+                let rate = transferred as f64 / started.elapsed().as_secs_f64().max(0.001);
+                let percent = total
+                    .filter(|t| *t > 0)
+                    .map(|t| downloaded as f64 / t as f64 * 100.0)
+                    .unwrap_or(0.0);
+                let mut progress = DownloadProgress::new(download_id, DownloadStatus::Downloading, percent);
+                progress.speed = Some(format_rate(rate));
+                progress.eta = total
+                    .filter(|_| rate > 0.0)
+                    .map(|t| format_eta((t.saturating_sub(downloaded) as f64 / rate) as u64));
+                self.report_progress(progress);
+            }
+        }
+        file.flush().await?;
+
+        match total {
+            Some(total) if downloaded < total => Err(TransferError::Retryable(anyhow!(
+                "Connection closed after {} of {} bytes",
+                downloaded,
+                total
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+// Muxing or re-encoding adds container overhead on top of the raw stream sizes.
+const MERGE_OVERHEAD: f64 = 0.05;
+const WAV_BYTES_PER_SECOND: u64 = 44_100 * 2 * 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiskSpacePolicy {
+    #[default]
+    Refuse,
+    Warn,
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DiskSpaceOptions {
+    pub policy: DiskSpacePolicy,
+    pub min_free_bytes: u64,
+}
+
+impl Default for DiskSpaceOptions {
+    fn default() -> Self {
+        Self {
+            policy: DiskSpacePolicy::default(),
+            min_free_bytes: 512 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeEstimate {
+    pub download_bytes: u64,
+    pub final_bytes: u64,
+}
+
+impl SizeEstimate {
+    pub fn staging_required(&self) -> u64 {
+        self.download_bytes + self.final_bytes
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceShortage {
+    pub path: String,
+    pub required: u64,
+    pub available: u64,
+}
+
+fn format_size(format: &Value, duration: Option<f64>) -> Option<u64> {
+    format["filesize"]
+        .as_u64()
+        .or_else(|| format["filesize_approx"].as_u64())
+        .or_else(|| {
+            let tbr = format["tbr"].as_f64()?;
+            Some((tbr * 1000.0 / 8.0 * duration?) as u64)
+        })
+}
+
+fn has_codec(format: &Value, key: &str) -> bool {
+    format[key]
+        .as_str()
+        .map(|codec| codec != "none")
+        .unwrap_or(false)
+}
+
+fn best_by<F>(formats: &[Value], filter: F, rank: fn(&Value) -> f64) -> Option<&Value>
+where
+    F: Fn(&Value) -> bool,
+{
+    formats
+        .iter()
+        .filter(|f| filter(f))
+        .max_by(|a, b| rank(a).total_cmp(&rank(b)))
+}
+
+fn audio_rank(format: &Value) -> f64 {
+    format["abr"]
+        .as_f64()
+        .or_else(|| format["tbr"].as_f64())
+        .unwrap_or(0.0)
+}
+
+fn video_rank(format: &Value) -> f64 {
+    format["height"].as_f64().unwrap_or(0.0) * 1_000_000.0 + format["tbr"].as_f64().unwrap_or(0.0)
+}
+
+fn audio_bitrate_kbps(quality: &str) -> u64 {
+    match quality {
+        "best" => 320,
+        "high" => 192,
+        "medium" => 128,
+        "low" => 96,
+        "worst" => 64,
+        _ => 192,
+    }
+}
+
+pub fn estimate_download_size(
+    video_data: &Value,
+    format: &str,
+    quality: &str,
+    max_height: u32,
+) -> Option<SizeEstimate> {
+    let formats = video_data["formats"].as_array()?;
+    let duration = video_data["duration"].as_f64();
+
+    let audio = best_by(
+        formats,
+        |f| has_codec(f, "acodec") && !has_codec(f, "vcodec"),
+        audio_rank,
+    );
+
+    match format {
+        "mp3" | "wav" => {
+            let download_bytes = audio.and_then(|a| format_size(a, duration))?;
+            let final_bytes = match (format, duration) {
+                ("wav", Some(seconds)) => (seconds * WAV_BYTES_PER_SECOND as f64) as u64,
+                (_, Some(seconds)) => (seconds * audio_bitrate_kbps(quality) as f64 * 1000.0 / 8.0) as u64,
+                (_, None) => download_bytes,
+            };
+            Some(SizeEstimate {
+                download_bytes,
+                final_bytes,
+            })
+        }
+        _ => {
+            let fits = |f: &Value| {
+                f["height"]
+                    .as_u64()
+                    .map(|h| h <= max_height as u64)
+                    .unwrap_or(false)
+            };
+            let video_only = best_by(
+                formats,
+                |f| has_codec(f, "vcodec") && !has_codec(f, "acodec") && fits(f),
+                video_rank,
+            );
+
+            let download_bytes = match (video_only, audio) {
+                (Some(video), Some(audio)) => {
+                    format_size(video, duration)? + format_size(audio, duration)?
+                }
+                _ => {
+                    let combined = best_by(
+                        formats,
+                        |f| has_codec(f, "vcodec") && has_codec(f, "acodec") && fits(f),
+                        video_rank,
+                    )?;
+                    format_size(combined, duration)?
+                }
+            };
+
+            Some(SizeEstimate {
+                download_bytes,
+                final_bytes: (download_bytes as f64 * (1.0 + MERGE_OVERHEAD)) as u64,
+            })
+        }
+    }
+}
+
+pub fn existing_ancestor(path: &Path) -> PathBuf {
+    let mut current = path.to_path_buf();
+    while !current.exists() {
+        match current.parent() {
+            Some(parent) => current = parent.to_path_buf(),
+            None => break,
+        }
+    }
+    current
+}
+
+pub fn available_space(path: &Path) -> Option<u64> {
+    fs4::available_space(existing_ancestor(path)).ok()
+}
+
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (
+        std::fs::metadata(existing_ancestor(a)),
+        std::fs::metadata(existing_ancestor(b)),
+    ) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    let root = |p: &Path| existing_ancestor(p).components().next().map(|c| c.as_os_str().to_owned());
+    root(a) == root(b)
+}
+
+pub fn check_free_space(
+    estimate: &SizeEstimate,
+    staging_dir: &Path,
+    output_dir: &Path,
+    min_free_bytes: u64,
+) -> Option<SpaceShortage> {
+    let mut requirements = vec![(staging_dir, estimate.staging_required())];
+    if !same_filesystem(staging_dir, output_dir) {
+        requirements.push((output_dir, estimate.final_bytes));
+    }
+
+    requirements.into_iter().find_map(|(path, required)| {
+        let required = required + min_free_bytes;
+        let available = available_space(path)?;
+        (available < required).then(|| SpaceShortage {
+            path: path.to_string_lossy().to_string(),
+            required,
+            available,
+        })
+    })
+}
+
+#[cfg(unix)]
+pub fn suspend_process_group(pid: u32) -> bool {
+    // SAFETY: kill(2) only takes integer arguments and touches no memory of
+    // ours. The negated pid targets the process group the child leads, as it
+    // was spawned with `process_group(0)`.
+    unsafe { libc::kill(-(pid as i32), libc::SIGSTOP) == 0 }
+}
+
+#[cfg(unix)]
+pub fn resume_process_group(pid: u32) -> bool {
+    // SAFETY: see `suspend_process_group`.
+    unsafe { libc::kill(-(pid as i32), libc::SIGCONT) == 0 }
+}
+
+#[cfg(unix)]
+pub fn kill_process_group(pid: u32) -> bool {
+    // SAFETY: see `suspend_process_group`.
+    unsafe { libc::kill(-(pid as i32), libc::SIGKILL) == 0 }
+}
@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use crate::direct;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{title}";
+
+const MAX_COMPONENT_BYTES: usize = 255;
+const MAX_COMPONENT_UTF16: usize = 255;
+// Headroom for the suffixes yt-dlp appends to intermediate files, e.g. ".f137.mp4.part".
+const INTERMEDIATE_SUFFIX_RESERVE: usize = 32;
+
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetOs {
+    Windows,
+    MacOs,
+    Unix,
+}
+
+impl TargetOs {
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            TargetOs::Windows
+        } else if cfg!(target_os = "macos") {
+            TargetOs::MacOs
+        } else {
+            TargetOs::Unix
+        }
+    }
+
+    fn is_forbidden(&self, c: char) -> bool {
+        if c == '/' || c == '\0' || c.is_control() {
+            return true;
+        }
+        match self {
+            TargetOs::Windows => matches!(c, '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*'),
+            TargetOs::MacOs => c == ':',
+            TargetOs::Unix => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilenameOptions {
+    pub template: String,
+    pub ascii_only: bool,
+}
+
+impl Default for FilenameOptions {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            ascii_only: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FilenameContext {
+    pub title: String,
+    pub uploader: Option<String>,
+    pub upload_date: Option<String>,
+    pub id: String,
+    pub playlist_title: Option<String>,
+    pub playlist_index: Option<u64>,
+    pub quality: String,
+    pub ext: String,
+}
+
+impl FilenameContext {
+    fn value(&self, placeholder: &str) -> Option<String> {
+        match placeholder {
+            "title" => Some(self.title.clone()),
+            "uploader" => Some(self.uploader.clone().unwrap_or_else(|| "Unknown".to_string())),
+            "upload_date" => Some(self.upload_date.clone().unwrap_or_default()),
+            "id" => Some(self.id.clone()),
+            "playlist_title" => Some(self.playlist_title.clone().unwrap_or_default()),
+            "playlist_index" => Some(
+                self.playlist_index
+                    .map(|i| format!("{:02}", i))
+                    .unwrap_or_default(),
+            ),
+            "quality" => Some(self.quality.clone()),
+            "ext" => Some(self.ext.clone()),
+            _ => None,
+        }
+    }
+}
+
+pub fn render_filename(options: &FilenameOptions, context: &FilenameContext) -> PathBuf {
+    render_filename_for(options, context, TargetOs::current())
+}
+
+pub fn render_filename_for(
+    options: &FilenameOptions,
+    context: &FilenameContext,
+    os: TargetOs,
+) -> PathBuf {
+    let template = if options.template.trim().is_empty() {
+        DEFAULT_FILENAME_TEMPLATE
+    } else {
+        options.template.as_str()
+    };
+    let template = template.strip_suffix(".{ext}").unwrap_or(template);
+
+    let mut components: Vec<String> = template
+        .split(['/', '\\'])
+        .map(|part| expand_placeholders(part, context))
+        .map(|part| sanitize_component(&part, options.ascii_only, os))
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    let file_name = components.pop().unwrap_or_default();
+    let file_name = finish_file_name(&file_name, &context.ext, options.ascii_only, os);
+
+    let mut path = PathBuf::new();
+    for dir in components {
+        path.push(truncate_component(&dir, MAX_COMPONENT_BYTES, os));
+    }
+    path.push(file_name);
+    path
+}
+
+/// Sanitizes a user-supplied file name into a single path component; separators
+/// and `..` never reach the filesystem as directory references.
+pub fn sanitize_file_name(name: &str, ascii_only: bool) -> String {
+    let os = TargetOs::current();
+    let cleaned = sanitize_component(&name.replace(['/', '\\'], "_"), ascii_only, os);
+    let cleaned = if cleaned.is_empty() {
+        "download".to_string()
+    } else {
+        cleaned
+    };
+    truncate_component(
+        &cleaned,
+        MAX_COMPONENT_BYTES - INTERMEDIATE_SUFFIX_RESERVE,
+        os,
+    )
+}
+
+/// `path` without its extension when that extension is a known media type,
+/// so dotted names such as "v1.2 final" are left whole.
+pub fn strip_media_extension(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if direct::is_media_extension(&ext.to_string_lossy()) => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `"{stem} ({index})"`, with `stem` shortened so the numbered name plus
+/// `.{ext}` stays within the same limits as a rendered file name.
+pub fn numbered_stem(stem: &str, index: u32, ext: &str) -> String {
+    let suffix = format!(" ({})", index);
+    let ext_len = if ext.is_empty() { 0 } else { ext.len() + 1 };
+    let budget = MAX_COMPONENT_BYTES
+        .saturating_sub(INTERMEDIATE_SUFFIX_RESERVE)
+        .saturating_sub(ext_len)
+        .saturating_sub(suffix.len());
+    format!("{}{}", truncate_component(stem, budget, TargetOs::current()), suffix)
+}
+
+fn expand_placeholders(part: &str, context: &FilenameContext) -> String {
+    let mut result = String::with_capacity(part.len());
+    let mut rest = part;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => match context.value(&after[..end]) {
+                Some(value) => {
+                    result.push_str(&value.replace(['/', '\\'], "_"));
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    result.push('{');
+                    rest = after;
+                }
+            },
+            None => {
+                result.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+pub fn sanitize_component(part: &str, ascii_only: bool, os: TargetOs) -> String {
+    let transliterated;
+    let part = if ascii_only {
+        transliterated = deunicode::deunicode(part);
+        transliterated.as_str()
+    } else {
+        part
+    };
+
+    let mut cleaned = String::with_capacity(part.len());
+    let mut last_was_space = false;
+    for c in part.chars() {
+        let c = if os.is_forbidden(c) { '_' } else { c };
+        let c = if c.is_whitespace() { ' ' } else { c };
+        if c == ' ' && last_was_space {
+            continue;
+        }
+        last_was_space = c == ' ';
+        cleaned.push(c);
+    }
+
+    let cleaned = cleaned
+        .trim()
+        .trim_end_matches(['.', ' '])
+        .trim_start_matches('-')
+        .to_string();
+
+    if cleaned.is_empty() || cleaned.chars().all(|c| c == '.') {
+        return String::new();
+    }
+
+    if os == TargetOs::Windows && is_windows_reserved(&cleaned) {
+        return format!("_{}", cleaned);
+    }
+
+    cleaned
+}
+
+fn is_windows_reserved(name: &str) -> bool {
+    let base = name.split('.').next().unwrap_or(name).trim_end();
+    WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+}
+
+fn finish_file_name(stem: &str, ext: &str, ascii_only: bool, os: TargetOs) -> String {
+    let stem = if stem.is_empty() { "download" } else { stem };
+    let ext = sanitize_component(ext, ascii_only, os);
+
+    let ext_len = if ext.is_empty() { 0 } else { ext.len() + 1 };
+    let budget = MAX_COMPONENT_BYTES
+        .saturating_sub(INTERMEDIATE_SUFFIX_RESERVE)
+        .saturating_sub(ext_len)
+        .max(16);
+    let stem = truncate_component(stem, budget, os);
+
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+fn truncate_component(component: &str, max_bytes: usize, os: TargetOs) -> String {
+    let mut end = 0;
+    let mut utf16_units = 0;
+    for (index, c) in component.char_indices() {
+        let next_end = index + c.len_utf8();
+        utf16_units += c.len_utf16();
+        let too_long = match os {
+            TargetOs::Windows => utf16_units > max_bytes.min(MAX_COMPONENT_UTF16),
+            _ => next_end > max_bytes,
+        };
+        if too_long {
+            break;
+        }
+        end = next_end;
+    }
+
+    component[..end].trim_end_matches(['.', ' ']).to_string()
+}
@@ -0,0 +1,258 @@
+use crate::youtube::parse_output_report;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// When set, every yt-dlp invocation is saved as a fixture in this directory.
+pub const RECORD_ENV: &str = "SNAPPER_RECORD_FIXTURES";
+/// When set, yt-dlp is replaced by the replay stand-in serving fixtures from
+/// this directory.
+pub const REPLAY_ENV: &str = "SNAPPER_REPLAY_FIXTURES";
+/// Overrides where the replay stand-in executable is looked up.
+pub const REPLAY_BIN_ENV: &str = "SNAPPER_REPLAY_BIN";
+
+/// Recording and replay are development tools. Release builds ignore the
+/// variables above, so a stray environment cannot swap yt-dlp out.
+pub const ENABLED: bool = cfg!(debug_assertions);
+
+const REPLAY_BIN_NAME: &str = "ytdlp-replay";
+const REPLAYED_FILE_CONTENTS: &[u8] = b"replayed by snapper fixtures";
+
+/// One recorded yt-dlp call. Paths that differ between runs (output, temp,
+/// cache and report locations) are stored as placeholders such as `{home}`,
+/// so a fixture recorded on one machine replays on another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    pub args: Vec<String>,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    /// Contents of the `--print-to-file` report, if the call wrote one.
+    #[serde(default)]
+    pub report: Option<String>,
+    /// Final output files named in the report; replay creates stand-ins.
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+/// Run-specific paths found in an argument list, paired with the placeholder
+/// they are recorded as.
+struct Substitutions {
+    pairs: Vec<(&'static str, String)>,
+    report_path: Option<PathBuf>,
+}
+
+impl Substitutions {
+    fn from_args(args: &[String]) -> Self {
+        let mut pairs = Vec::new();
+        let mut report_path = None;
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--paths" | "-P" => {
+                    if let Some(value) = iter.next() {
+                        if let Some(home) = value.strip_prefix("home:") {
+                            pairs.push(("{home}", home.to_string()));
+                        } else if let Some(temp) = value.strip_prefix("temp:") {
+                            pairs.push(("{temp}", temp.to_string()));
+                        }
+                    }
+                }
+                "--cache-dir" => {
+                    if let Some(value) = iter.next() {
+                        pairs.push(("{cache}", value.to_string()));
+                    }
+                }
+                "--load-info-json" => {
+                    if let Some(value) = iter.next() {
+                        pairs.push(("{info_json}", value.to_string()));
+                    }
+                }
+                "--print-to-file" => {
+                    iter.next();
+                    if let Some(value) = iter.next() {
+                        // yt-dlp expands templates in the file name, so `%` is
+                        // doubled on the command line.
+                        let path = value.replace("%%", "%");
+                        report_path.get_or_insert_with(|| PathBuf::from(&path));
+                        pairs.push(("{report}", value.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        pairs.retain(|(_, value)| !value.is_empty());
+        // Longest first so a path nested in another is not half-replaced.
+        pairs.sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+        pairs.dedup();
+        Self { pairs, report_path }
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        self.pairs
+            .iter()
+            .fold(text.to_string(), |text, (placeholder, value)| text.replace(value.as_str(), placeholder))
+    }
+
+    fn expand(&self, text: &str) -> String {
+        self.pairs
+            .iter()
+            .fold(text.to_string(), |text, (placeholder, value)| text.replace(placeholder, value))
+    }
+}
+
+pub fn recording_dir() -> Option<PathBuf> {
+    std::env::var_os(RECORD_ENV).filter(|_| ENABLED).map(PathBuf::from)
+}
+
+pub fn replay_dir() -> Option<PathBuf> {
+    std::env::var_os(REPLAY_ENV).filter(|_| ENABLED).map(PathBuf::from)
+}
+
+/// Location of the replay stand-in, which is built next to the app binary.
+pub fn replay_binary() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os(REPLAY_BIN_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+    let name = if cfg!(windows) {
+        format!("{}.exe", REPLAY_BIN_NAME)
+    } else {
+        REPLAY_BIN_NAME.to_string()
+    };
+    let path = std::env::current_exe()?.with_file_name(name);
+    if path.exists() {
+        Ok(path)
+    } else {
+        Err(anyhow!("Replay executable not found at {}", path.display()))
+    }
+}
+
+pub fn command_args(cmd: &std::process::Command) -> Vec<String> {
+    cmd.get_args().map(|arg| arg.to_string_lossy().to_string()).collect()
+}
+
+fn fixture_path(dir: &Path, normalized_args: &[String]) -> PathBuf {
+    let digest = Sha256::digest(normalized_args.join("\0").as_bytes());
+    dir.join(format!("{}.json", &hex::encode(digest)[..16]))
+}
+
+/// Saves a finished call when recording is enabled. Failures are logged and
+/// never affect the download itself.
+pub fn record(args: &[String], output: &std::process::Output) {
+    let Some(dir) = recording_dir() else {
+        return;
+    };
+    match record_to(&dir, args, output) {
+        Ok(path) => println!("Recorded yt-dlp fixture: {}", path.display()),
+        Err(e) => eprintln!("Warning: Failed to record yt-dlp fixture: {}", e),
+    }
+}
+
+pub fn record_to(dir: &Path, args: &[String], output: &std::process::Output) -> Result<PathBuf> {
+    let substitutions = Substitutions::from_args(args);
+    let report = substitutions
+        .report_path
+        .as_deref()
+        .and_then(|path| std::fs::read_to_string(path).ok());
+    let files = report
+        .as_deref()
+        .and_then(|report| parse_output_report(report).ok())
+        .map(|output| {
+            output
+                .files
+                .iter()
+                .map(|file| substitutions.normalize(&file.to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let fixture = Fixture {
+        args: args.iter().map(|arg| substitutions.normalize(arg)).collect(),
+        stdout: substitutions.normalize(&String::from_utf8_lossy(&output.stdout)),
+        stderr: substitutions.normalize(&String::from_utf8_lossy(&output.stderr)),
+        exit_code: output.status.code().unwrap_or(-1),
+        report: report.map(|report| substitutions.normalize(&report)),
+        files,
+    };
+
+    std::fs::create_dir_all(dir)?;
+    let path = fixture_path(dir, &fixture.args);
+    std::fs::write(&path, serde_json::to_string_pretty(&fixture)?)?;
+    Ok(path)
+}
+
+pub fn load_fixture(dir: &Path, args: &[String]) -> Result<Fixture> {
+    let substitutions = Substitutions::from_args(args);
+    let normalized: Vec<String> = args.iter().map(|arg| substitutions.normalize(arg)).collect();
+    let path = fixture_path(dir, &normalized);
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|_| anyhow!("No fixture recorded for: {}", normalized.join(" ")))?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Plays a fixture back for `args`: recreates the report and output files the
+/// original run produced and returns the stdout, stderr and exit code to emit.
+pub fn replay_from(dir: &Path, args: &[String]) -> Result<(String, String, i32)> {
+    let substitutions = Substitutions::from_args(args);
+    let fixture = load_fixture(dir, args)?;
+
+    for file in &fixture.files {
+        let path = PathBuf::from(substitutions.expand(file));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, REPLAYED_FILE_CONTENTS)?;
+    }
+    if let (Some(report), Some(report_path)) = (&fixture.report, &substitutions.report_path) {
+        std::fs::write(report_path, substitutions.expand(report))?;
+    }
+
+    Ok((
+        substitutions.expand(&fixture.stdout),
+        substitutions.expand(&fixture.stderr),
+        fixture.exit_code,
+    ))
+}
+
+/// Entry point of the replay stand-in executable.
+pub fn replay_main<I, S>(args: I) -> i32
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<String> = args
+        .into_iter()
+        .map(|arg| arg.as_ref().to_string_lossy().to_string())
+        .collect();
+
+    if args.iter().any(|arg| arg == "--version") {
+        println!("{} (fixture replay)", REPLAY_BIN_NAME);
+        return 0;
+    }
+
+    if !ENABLED {
+        eprintln!("ERROR: fixture replay is only available in debug builds");
+        return 2;
+    }
+    let Some(dir) = replay_dir() else {
+        eprintln!("ERROR: {} is not set", REPLAY_ENV);
+        return 2;
+    };
+
+    match replay_from(&dir, &args) {
+        Ok((stdout, stderr, exit_code)) => {
+            let _ = std::io::stdout().write_all(stdout.as_bytes());
+            let _ = std::io::stderr().write_all(stderr.as_bytes());
+            exit_code
+        }
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            2
+        }
+    }
+}
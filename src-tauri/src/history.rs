@@ -0,0 +1,670 @@
+use crate::stats::{
+    DownloadStats, FailureStats, StatsPoint, StatsQuery, UploaderStats, DEFAULT_TOP_UPLOADERS,
+};
+use crate::urls;
+use crate::youtube::{DownloadHistory, DownloadStatus, ErrorCategory};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+pub const HISTORY_DB_FILE: &str = "history.db";
+
+// Each entry is kept whole as JSON in `data`; the other columns copy the
+// fields lookups, filters and ordering need so they can be indexed.
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE downloads (
+        id TEXT PRIMARY KEY NOT NULL,
+        url TEXT NOT NULL,
+        video_id TEXT,
+        title TEXT NOT NULL,
+        status TEXT NOT NULL,
+        format TEXT NOT NULL,
+        quality TEXT NOT NULL,
+        extractor TEXT,
+        file_path TEXT,
+        downloaded_at INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX idx_downloads_url ON downloads (url);
+    CREATE INDEX idx_downloads_video_id ON downloads (video_id);
+    CREATE INDEX idx_downloads_downloaded_at ON downloads (downloaded_at);
+    CREATE INDEX idx_downloads_status ON downloads (status);
+"#, r#"
+    CREATE INDEX idx_downloads_format ON downloads (format);
+    CREATE INDEX idx_downloads_extractor ON downloads (extractor);
+"#, r#"
+    ALTER TABLE downloads ADD COLUMN uploader TEXT;
+    ALTER TABLE downloads ADD COLUMN file_size INTEGER;
+    ALTER TABLE downloads ADD COLUMN duration INTEGER;
+    ALTER TABLE downloads ADD COLUMN average_speed REAL;
+    ALTER TABLE downloads ADD COLUMN error_category TEXT;
+    UPDATE downloads SET
+        uploader = json_extract(data, '$.uploader'),
+        file_size = json_extract(data, '$.file_size'),
+        duration = json_extract(data, '$.duration'),
+        average_speed = json_extract(data, '$.average_speed'),
+        error_category = json_extract(data, '$.error.category');
+    CREATE INDEX idx_downloads_uploader ON downloads (uploader);
+"#];
+
+/// How much history to keep. Entries that are still queued or downloading are
+/// never pruned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RetentionPolicy {
+    #[default]
+    Unlimited,
+    MaxEntries {
+        count: usize,
+    },
+    MaxAgeDays {
+        days: u32,
+    },
+}
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySort {
+    #[default]
+    DownloadedAt,
+    Title,
+    Status,
+    Format,
+}
+
+impl HistorySort {
+    fn column(self) -> &'static str {
+        match self {
+            HistorySort::DownloadedAt => "downloaded_at",
+            HistorySort::Title => "title COLLATE NOCASE",
+            HistorySort::Status => "status",
+            HistorySort::Format => "format",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+/// Filters for `query_history`. Empty lists and missing fields match all
+/// entries; `search` looks at title, uploader and URL.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub search: Option<String>,
+    pub statuses: Vec<DownloadStatus>,
+    pub formats: Vec<String>,
+    pub qualities: Vec<String>,
+    pub extractors: Vec<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub sort: HistorySort,
+    pub order: SortOrder,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub entries: Vec<DownloadHistory>,
+    /// Entries matching the query, ignoring offset and limit.
+    pub total: usize,
+    /// Entries in the whole history.
+    pub unfiltered_total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn in_clause(column: &str, values: &[String], params: &mut Vec<SqlValue>) -> String {
+    let placeholders = vec!["?"; values.len()].join(", ");
+    params.extend(values.iter().cloned().map(SqlValue::Text));
+    format!("{} IN ({})", column, placeholders)
+}
+
+fn and_where(filter: &str, condition: &str) -> String {
+    if filter.is_empty() {
+        format!(" WHERE {}", condition)
+    } else {
+        format!("{} AND {}", filter, condition)
+    }
+}
+
+/// Adds empty points for periods without downloads, so the series covers the
+/// requested range (or the span of the data) without gaps.
+fn fill_series(query: &StatsQuery, points: Vec<StatsPoint>) -> Vec<StatsPoint> {
+    let from = query
+        .from
+        .map(StatsQuery::local_date)
+        .or_else(|| points.first().map(|p| p.period));
+    let to = query
+        .to
+        .or(query.from.map(|_| Utc::now()))
+        .map(StatsQuery::local_date)
+        .or_else(|| points.last().map(|p| p.period));
+    let (Some(from), Some(to)) = (from, to) else {
+        return points;
+    };
+
+    let mut by_period: std::collections::HashMap<_, _> =
+        points.into_iter().map(|point| (point.period, point)).collect();
+    query
+        .interval
+        .periods(from, to)
+        .into_iter()
+        .map(|period| by_period.remove(&period).unwrap_or_else(|| StatsPoint::empty(period)))
+        .collect()
+}
+
+impl HistoryQuery {
+    /// WHERE clause and its parameters for this query's filters.
+    fn filter(&self) -> (String, Vec<SqlValue>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+
+        if let Some(search) = self.search.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+            conditions.push(
+                "(title LIKE ? ESCAPE '\\' OR url LIKE ? ESCAPE '\\' OR uploader LIKE ? ESCAPE '\\')"
+                    .to_string(),
+            );
+            let pattern = like_pattern(search);
+            params.extend(std::iter::repeat_n(SqlValue::Text(pattern), 3));
+        }
+        if !self.statuses.is_empty() {
+            let statuses: Vec<String> = self.statuses.iter().map(status_name).collect();
+            conditions.push(in_clause("status", &statuses, &mut params));
+        }
+        if !self.formats.is_empty() {
+            conditions.push(in_clause("format", &self.formats, &mut params));
+        }
+        if !self.qualities.is_empty() {
+            conditions.push(in_clause("quality", &self.qualities, &mut params));
+        }
+        if !self.extractors.is_empty() {
+            conditions.push(in_clause("extractor", &self.extractors, &mut params));
+        }
+        if let Some(from) = self.from {
+            conditions.push("downloaded_at >= ?".to_string());
+            params.push(SqlValue::Integer(from.timestamp_millis()));
+        }
+        if let Some(to) = self.to {
+            conditions.push("downloaded_at <= ?".to_string());
+            params.push(SqlValue::Integer(to.timestamp_millis()));
+        }
+
+        if conditions.is_empty() {
+            (String::new(), params)
+        } else {
+            (format!(" WHERE {}", conditions.join(" AND ")), params)
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+/// Whether an entry belongs to a download that has not finished yet.
+pub fn is_active(status: &DownloadStatus) -> bool {
+    matches!(
+        status,
+        DownloadStatus::Pending | DownloadStatus::Downloading | DownloadStatus::Paused
+    )
+}
+
+pub fn status_name(status: &DownloadStatus) -> String {
+    enum_name(status)
+}
+
+/// Serialized name of a unit enum variant, as stored in the database.
+fn enum_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+fn video_id(entry: &DownloadHistory) -> Option<String> {
+    entry
+        .video_id
+        .clone()
+        .or_else(|| urls::analyze_url(&entry.url).ok().and_then(|analysis| analysis.video_id))
+}
+
+pub struct HistoryStore {
+    conn: Mutex<Connection>,
+}
+
+impl HistoryStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| anyhow!("Failed to open history database {}: {}", path.display(), e))?;
+        // Several download tasks write at once; wait for the lock instead of
+        // failing with SQLITE_BUSY.
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        Self::from_connection(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        Self::migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn migrate(conn: &mut Connection) -> Result<()> {
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|e| anyhow!("History database lock poisoned: {}", e))
+    }
+
+    fn upsert_with(conn: &Connection, entry: &DownloadHistory) -> Result<()> {
+        conn.execute(
+            "INSERT INTO downloads
+                (id, url, video_id, title, status, format, quality, extractor, file_path, downloaded_at,
+                 uploader, file_size, duration, average_speed, error_category, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+             ON CONFLICT(id) DO UPDATE SET
+                url = excluded.url,
+                video_id = excluded.video_id,
+                title = excluded.title,
+                status = excluded.status,
+                format = excluded.format,
+                quality = excluded.quality,
+                extractor = excluded.extractor,
+                file_path = excluded.file_path,
+                downloaded_at = excluded.downloaded_at,
+                uploader = excluded.uploader,
+                file_size = excluded.file_size,
+                duration = excluded.duration,
+                average_speed = excluded.average_speed,
+                error_category = excluded.error_category,
+                data = excluded.data",
+            params![
+                entry.id,
+                entry.url,
+                video_id(entry),
+                entry.title,
+                status_name(&entry.status),
+                entry.format,
+                entry.quality,
+                entry.extractor,
+                entry.file_path,
+                entry.downloaded_at.timestamp_millis(),
+                entry.uploader,
+                entry.file_size.map(|size| size as i64),
+                entry.duration.map(|duration| duration as i64),
+                entry.average_speed,
+                entry.error.as_ref().map(|error| enum_name(&error.category)),
+                serde_json::to_string(entry)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn upsert(&self, entry: &DownloadHistory) -> Result<()> {
+        let conn = self.conn()?;
+        Self::upsert_with(&conn, entry)
+    }
+
+    /// Imports entries in one transaction, keeping any that already exist.
+    pub fn import(&self, entries: &[DownloadHistory]) -> Result<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut imported = 0;
+        for entry in entries {
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM downloads WHERE id = ?1)",
+                [&entry.id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                Self::upsert_with(&tx, entry)?;
+                imported += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(imported)
+    }
+
+    /// Applies `change` to the stored entry inside a transaction, so concurrent
+    /// updates to the same entry cannot overwrite each other.
+    pub fn update<F>(&self, id: &str, change: F) -> Result<Option<DownloadHistory>>
+    where
+        F: FnOnce(&mut DownloadHistory),
+    {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let data: Option<String> = tx
+            .query_row("SELECT data FROM downloads WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        let mut entry: DownloadHistory = serde_json::from_str(&data)?;
+        change(&mut entry);
+        Self::upsert_with(&tx, &entry)?;
+        tx.commit()?;
+        Ok(Some(entry))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<DownloadHistory>> {
+        let data: Option<String> = self
+            .conn()?
+            .query_row("SELECT data FROM downloads WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        data.map(|data| Ok(serde_json::from_str(&data)?)).transpose()
+    }
+
+    pub fn find_by_url(&self, url: &str) -> Result<Vec<DownloadHistory>> {
+        self.select(
+            "SELECT data FROM downloads WHERE url = ?1 ORDER BY downloaded_at DESC",
+            [url],
+        )
+    }
+
+    pub fn find_by_video_id(&self, video_id: &str) -> Result<Vec<DownloadHistory>> {
+        self.select(
+            "SELECT data FROM downloads WHERE video_id = ?1 ORDER BY downloaded_at DESC",
+            [video_id],
+        )
+    }
+
+    pub fn find_by_file_path(&self, file_path: &str) -> Result<Vec<DownloadHistory>> {
+        self.select(
+            "SELECT data FROM downloads WHERE file_path = ?1 ORDER BY downloaded_at DESC",
+            [file_path],
+        )
+    }
+
+    /// Entries whose stored status counts as active under `is_active`.
+    pub fn find_active(&self) -> Result<Vec<DownloadHistory>> {
+        let statuses = [
+            DownloadStatus::Pending,
+            DownloadStatus::Downloading,
+            DownloadStatus::Paused,
+        ]
+        .map(|status| status_name(&status));
+        self.select(
+            "SELECT data FROM downloads WHERE status IN (?1, ?2, ?3) ORDER BY downloaded_at DESC",
+            statuses,
+        )
+    }
+
+    /// All entries, newest first.
+    pub fn list(&self) -> Result<Vec<DownloadHistory>> {
+        self.select("SELECT data FROM downloads ORDER BY downloaded_at DESC", [])
+    }
+
+    pub fn query(&self, query: &HistoryQuery) -> Result<HistoryPage> {
+        let (filter, mut params) = query.filter();
+        let limit = query.page_size();
+        let conn = self.conn()?;
+
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM downloads{}", filter),
+            params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+        let unfiltered_total: i64 =
+            conn.query_row("SELECT COUNT(*) FROM downloads", [], |row| row.get(0))?;
+
+        let direction = match query.order {
+            SortOrder::Ascending => "ASC",
+            SortOrder::Descending => "DESC",
+        };
+        // `id` breaks ties so pages do not overlap when sort values repeat.
+        let sql = format!(
+            "SELECT data FROM downloads{} ORDER BY {} {}, id {} LIMIT ? OFFSET ?",
+            filter,
+            query.sort.column(),
+            direction,
+            direction
+        );
+        params.push(SqlValue::Integer(limit as i64));
+        params.push(SqlValue::Integer(query.offset as i64));
+
+        let mut statement = conn.prepare(&sql)?;
+        let rows = statement.query_map(params_from_iter(params.iter()), |row| row.get::<_, String>(0))?;
+        let entries = rows
+            .map(|data| Ok(serde_json::from_str(&data?)?))
+            .collect::<Result<Vec<DownloadHistory>>>()?;
+
+        Ok(HistoryPage {
+            entries,
+            total: total as usize,
+            unfiltered_total: unfiltered_total as usize,
+            offset: query.offset,
+            limit,
+        })
+    }
+
+    /// Aggregates the entries downloaded within the query's range. Every
+    /// figure is computed in SQL from the indexed columns, so no stored entry
+    /// is deserialized.
+    pub fn statistics(&self, query: &StatsQuery) -> Result<DownloadStats> {
+        let (filter, params) = HistoryQuery {
+            from: query.from,
+            to: query.to,
+            ..Default::default()
+        }
+        .filter();
+        let completed_status = status_name(&DownloadStatus::Completed);
+        let failed_status = status_name(&DownloadStatus::Failed);
+        let completed_filter = and_where(&filter, &format!("status = '{}'", completed_status));
+        let conn = self.conn()?;
+        let mut stats = DownloadStats {
+            interval: query.interval,
+            ..Default::default()
+        };
+
+        let mut statement = conn.prepare(&format!(
+            "SELECT status, COUNT(*) FROM downloads{} GROUP BY status",
+            filter
+        ))?;
+        let mut rows = statement.query(params_from_iter(params.iter()))?;
+        while let Some(row) = rows.next()? {
+            let (status, count): (String, i64) = (row.get(0)?, row.get(1)?);
+            let count = count as usize;
+            stats.total += count;
+            if status == completed_status {
+                stats.completed = count;
+            } else if status == failed_status {
+                stats.failed = count;
+            } else if status == status_name(&DownloadStatus::Downloading) {
+                stats.downloading = count;
+            }
+        }
+        if stats.completed + stats.failed > 0 {
+            stats.success_rate = Some(stats.completed as f64 / (stats.completed + stats.failed) as f64);
+        }
+
+        let mut statement = conn.prepare(&format!(
+            "SELECT format, COUNT(*) FROM downloads{} GROUP BY format ORDER BY COUNT(*) DESC, format",
+            filter
+        ))?;
+        let formats = statement
+            .query_map(params_from_iter(params.iter()), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        stats.most_used_format = formats.first().map(|(format, _)| format.clone());
+        stats.formats_breakdown = formats.into_iter().collect();
+
+        // Speed is weighted by size: total bytes over total transfer time.
+        let (total_bytes, total_duration, timed_bytes, timed_seconds): (i64, i64, Option<f64>, Option<f64>) =
+            conn.query_row(
+                &format!(
+                    "SELECT COALESCE(SUM(file_size), 0), COALESCE(SUM(duration), 0),
+                        SUM(CASE WHEN average_speed > 0 THEN file_size END),
+                        SUM(CASE WHEN average_speed > 0 THEN file_size / average_speed END)
+                     FROM downloads{}",
+                    completed_filter
+                ),
+                params_from_iter(params.iter()),
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+        stats.total_bytes = total_bytes as u64;
+        stats.total_duration_seconds = total_duration as u64;
+        if let (Some(bytes), Some(seconds)) = (timed_bytes, timed_seconds.filter(|s| *s > 0.0)) {
+            stats.average_speed = Some(bytes / seconds);
+        }
+
+        let mut statement = conn.prepare(&format!(
+            "SELECT uploader, COUNT(*),
+                COALESCE(SUM(CASE WHEN status = '{}' THEN file_size END), 0)
+             FROM downloads{}
+             GROUP BY uploader ORDER BY COUNT(*) DESC, uploader LIMIT {}",
+            completed_status,
+            and_where(&filter, "uploader IS NOT NULL AND uploader <> ''"),
+            query.top_uploaders.unwrap_or(DEFAULT_TOP_UPLOADERS)
+        ))?;
+        stats.top_uploaders = statement
+            .query_map(params_from_iter(params.iter()), |row| {
+                Ok(UploaderStats {
+                    uploader: row.get(0)?,
+                    downloads: row.get::<_, i64>(1)? as usize,
+                    bytes: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Failures recorded before error details were kept count as `other`.
+        let mut statement = conn.prepare(&format!(
+            "SELECT COALESCE(error_category, '{}'), COUNT(*) FROM downloads{}
+             GROUP BY 1 ORDER BY COUNT(*) DESC, 1",
+            enum_name(&ErrorCategory::Other),
+            and_where(&filter, &format!("status = '{}'", failed_status))
+        ))?;
+        let failures = statement
+            .query_map(params_from_iter(params.iter()), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        stats.failure_reasons = failures
+            .into_iter()
+            .map(|(category, count)| FailureStats {
+                category: serde_json::from_value(serde_json::Value::String(category)).unwrap_or_default(),
+                count,
+            })
+            .collect();
+
+        let mut statement = conn.prepare(&format!(
+            "SELECT {period} AS period, COUNT(*),
+                SUM(status = '{completed}'), SUM(status = '{failed}'),
+                COALESCE(SUM(CASE WHEN status = '{completed}' THEN file_size END), 0),
+                COALESCE(SUM(CASE WHEN status = '{completed}' THEN duration END), 0)
+             FROM downloads{filter} GROUP BY period ORDER BY period",
+            period = query.interval.sql_period(),
+            completed = completed_status,
+            failed = failed_status,
+            filter = filter
+        ))?;
+        let points = statement
+            .query_map(params_from_iter(params.iter()), |row| {
+                let period: String = row.get(0)?;
+                Ok(StatsPoint {
+                    period: NaiveDate::parse_from_str(&period, "%Y-%m-%d").map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+                    })?,
+                    downloads: row.get::<_, i64>(1)? as usize,
+                    completed: row.get::<_, i64>(2)? as usize,
+                    failed: row.get::<_, i64>(3)? as usize,
+                    bytes: row.get::<_, i64>(4)? as u64,
+                    duration_seconds: row.get::<_, i64>(5)? as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        stats.series = fill_series(query, points);
+
+        Ok(stats)
+    }
+
+    fn select<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<Vec<DownloadHistory>> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(params, |row| row.get::<_, String>(0))?;
+        rows.map(|data| Ok(serde_json::from_str(&data?)?)).collect()
+    }
+
+    pub fn delete(&self, ids: &[String]) -> Result<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut removed = 0;
+        for id in ids {
+            removed += tx.execute("DELETE FROM downloads WHERE id = ?1", [id])?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    pub fn clear(&self) -> Result<usize> {
+        Ok(self.conn()?.execute("DELETE FROM downloads", [])?)
+    }
+
+    /// Deletes finished entries the policy no longer covers and returns how
+    /// many were removed.
+    pub fn apply_retention(&self, policy: RetentionPolicy) -> Result<usize> {
+        let active = [
+            status_name(&DownloadStatus::Pending),
+            status_name(&DownloadStatus::Downloading),
+            status_name(&DownloadStatus::Paused),
+        ];
+        let conn = self.conn()?;
+        let removed = match policy {
+            RetentionPolicy::Unlimited => 0,
+            RetentionPolicy::MaxEntries { count } => conn.execute(
+                "DELETE FROM downloads
+                 WHERE status NOT IN (?1, ?2, ?3)
+                   AND id NOT IN (SELECT id FROM downloads ORDER BY downloaded_at DESC LIMIT ?4)",
+                params![active[0], active[1], active[2], count as i64],
+            )?,
+            RetentionPolicy::MaxAgeDays { days } => {
+                let cutoff = Utc::now() - Duration::days(days as i64);
+                conn.execute(
+                    "DELETE FROM downloads WHERE status NOT IN (?1, ?2, ?3) AND downloaded_at < ?4",
+                    params![active[0], active[1], active[2], cutoff.timestamp_millis()],
+                )?
+            }
+        };
+        Ok(removed)
+    }
+}
@@ -0,0 +1,189 @@
+use crate::security;
+use crate::youtube::{DownloadHistory, DownloadStatus};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HookEvent {
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookConfig {
+    pub name: String,
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub events: Vec<HookEvent>,
+    #[serde(default = "default_hook_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_hook_enabled")]
+    pub enabled: bool,
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    30
+}
+
+fn default_hook_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookResult {
+    pub name: String,
+    pub event: HookEvent,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+impl HookEvent {
+    pub fn for_status(status: &DownloadStatus) -> Option<Self> {
+        match status {
+            DownloadStatus::Completed => Some(HookEvent::Completed),
+            DownloadStatus::Failed => Some(HookEvent::Failed),
+            _ => None,
+        }
+    }
+}
+
+pub async fn run_hooks(hooks: &[HookConfig], history: &DownloadHistory) -> Vec<HookResult> {
+    let Some(event) = HookEvent::for_status(&history.status) else {
+        return Vec::new();
+    };
+
+    let mut results = Vec::new();
+    for hook in hooks
+        .iter()
+        .filter(|h| h.enabled && h.events.contains(&event))
+    {
+        println!("Running hook '{}' for download {}", hook.name, history.id);
+        let result = run_hook(hook, event, history).await;
+        if let Some(error) = &result.error {
+            eprintln!("Hook '{}' failed: {}", hook.name, error);
+        }
+        results.push(result);
+    }
+
+    results
+}
+
+async fn run_hook(hook: &HookConfig, event: HookEvent, history: &DownloadHistory) -> HookResult {
+    let started_at = Utc::now();
+    let mut result = HookResult {
+        name: hook.name.clone(),
+        event,
+        exit_code: None,
+        stdout: String::new(),
+        stderr: String::new(),
+        timed_out: false,
+        error: None,
+        started_at,
+        finished_at: started_at,
+    };
+
+    match execute_hook(hook, event, history).await {
+        Ok(HookOutcome::Finished(output)) => {
+            result.exit_code = output.status.code();
+            result.stdout = truncate_output(&output.stdout);
+            result.stderr = truncate_output(&output.stderr);
+            if !output.status.success() {
+                result.error = Some(format!("Hook exited with {}", output.status));
+            }
+        }
+        Ok(HookOutcome::TimedOut) => {
+            result.timed_out = true;
+            result.error = Some(format!("Hook timed out after {}s", hook.timeout_secs));
+        }
+        Err(e) => {
+            result.error = Some(e.to_string());
+        }
+    }
+
+    result.finished_at = Utc::now();
+    result
+}
+
+enum HookOutcome {
+    Finished(std::process::Output),
+    TimedOut,
+}
+
+async fn execute_hook(
+    hook: &HookConfig,
+    event: HookEvent,
+    history: &DownloadHistory,
+) -> Result<HookOutcome> {
+    let payload = serde_json::to_vec(history)?;
+
+    let mut cmd = security::clean_async_command(&hook.program);
+    cmd.args(&hook.args)
+        .envs(hook_env(event, history))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start hook '{}': {}", hook.program, e))?;
+
+    // The payload is written while the output is read, all under the
+    // timeout, so a hook that ignores stdin cannot stall on a full pipe.
+    let stdin = child.stdin.take();
+    let write_payload = async move {
+        if let Some(mut stdin) = stdin {
+            if let Err(e) = stdin.write_all(&payload).await {
+                eprintln!("Warning: Failed to write payload to hook '{}': {}", hook.name, e);
+            }
+            // Dropping stdin here closes it, so the hook sees end of input.
+        }
+    };
+    let run = async { tokio::join!(write_payload, child.wait_with_output()).1 };
+
+    let timeout = Duration::from_secs(hook.timeout_secs.max(1));
+    match tokio::time::timeout(timeout, run).await {
+        Ok(output) => Ok(HookOutcome::Finished(output?)),
+        Err(_) => Ok(HookOutcome::TimedOut),
+    }
+}
+
+pub fn hook_env(event: HookEvent, history: &DownloadHistory) -> Vec<(String, String)> {
+    vec![
+        ("SNAPPER_EVENT".to_string(), format!("{:?}", event)),
+        ("SNAPPER_ID".to_string(), history.id.clone()),
+        ("SNAPPER_TITLE".to_string(), history.title.clone()),
+        ("SNAPPER_URL".to_string(), history.url.clone()),
+        (
+            "SNAPPER_FILE_PATH".to_string(),
+            history.file_path.clone().unwrap_or_default(),
+        ),
+        ("SNAPPER_FORMAT".to_string(), history.format.clone()),
+        ("SNAPPER_STATUS".to_string(), format!("{:?}", history.status)),
+    ]
+}
+
+fn truncate_output(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    if text.len() <= MAX_CAPTURED_OUTPUT {
+        return text.to_string();
+    }
+
+    let mut end = MAX_CAPTURED_OUTPUT;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n[output truncated]", &text[..end])
+}
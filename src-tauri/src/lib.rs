@@ -1,7 +1,30 @@
+pub mod backend;
+pub mod cache;
 mod commands;
+pub mod crate_backend;
+pub mod direct;
+pub mod diskspace;
+pub mod filename;
+pub mod fixtures;
+pub mod history;
+pub mod hooks;
+pub mod manager;
+pub mod metadata_cache;
+pub mod postprocess;
+pub mod reconcile;
+#[cfg(test)]
+mod scripted;
+pub mod security;
+pub mod sites;
+pub mod staging;
+pub mod stats;
+pub mod trash;
+pub mod urls;
+pub mod webhooks;
 pub mod youtube;
 
 use commands::*;
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -9,11 +32,36 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
+        .manage(manager::DownloadManager::new())
+        .setup(|app| {
+            let db_path = app.path().app_data_dir()?.join(history::HISTORY_DB_FILE);
+            app.manage(history::HistoryStore::open(&db_path)?);
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                migrate_legacy_history(&handle).await;
+                cleanup_staging_dirs(&handle).await;
+                restore_download_queue(&handle).await;
+                verify_history_in_background(&handle).await;
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             validate_youtube_url,
+            identify_site,
+            analyze_url,
             get_video_info,
             download_video,
+            cancel_download,
+            get_download_queue,
+            list_formats,
             get_download_history,
+            query_history,
+            delete_history_entries,
+            redownload_entry,
+            open_history_file,
+            reveal_history_file,
+            verify_history,
             get_download_status,
             clear_download_history,
             get_default_download_path,
@@ -22,9 +70,12 @@ pub fn run() {
             get_supported_qualities,
             check_dependencies,
             get_download_statistics,
+            export_download_statistics_csv,
             save_settings,
             load_settings,
-            get_debug_info
+            get_debug_info,
+            get_cache_info,
+            clear_cache
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -35,17 +86,16 @@ mod tests {
     use super::youtube::*;
     use std::path::PathBuf;
 
-    #[tokio::test]
-    async fn test_check_dependencies() {
-        let result = YouTubeDownloader::check_dependencies().await;
-        println!("Dependencies check result: {:?}", result);
-        assert!(result.is_ok(), "Dependencies should be available");
-    }
-
     #[tokio::test]
     async fn test_validate_url() {
-        let output_dir = PathBuf::from("/tmp");
-        let downloader = YouTubeDownloader::new(output_dir).expect("Failed to create downloader");
+        use crate::backend::Binaries;
+
+        let binaries = Binaries {
+            yt_dlp: PathBuf::from("yt-dlp"),
+            ffmpeg: PathBuf::from("ffmpeg"),
+            yt_dlp_version: None,
+        };
+        let downloader = YouTubeDownloader::with_binaries(PathBuf::from("/tmp"), binaries);
 
         assert!(downloader.validate_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
         assert!(downloader.validate_url("https://youtu.be/dQw4w9WgXcQ"));
@@ -53,34 +103,139 @@ mod tests {
 
         assert!(!downloader.validate_url("https://example.com"));
         assert!(!downloader.validate_url("not_a_url"));
+
+        assert!(downloader.validate_url("https://m.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert!(downloader.validate_url("https://music.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert!(downloader.validate_url("https://www.youtube.com/live/dQw4w9WgXcQ"));
+        assert!(downloader.validate_url("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ"));
+        assert!(!downloader.validate_url("https://evil.example/?q=youtube.com/watch"));
+        assert!(!downloader.validate_url("https://youtube.com.evil.example/watch?v=dQw4w9WgXcQ"));
     }
 
-    #[tokio::test]
-    async fn test_get_video_info() {
-        let output_dir = PathBuf::from("/tmp");
-        let downloader = YouTubeDownloader::new(output_dir).expect("Failed to create downloader");
-
-        let test_url = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
-
-        match downloader.get_video_info(test_url).await {
-            Ok(video_info) => {
-                println!("Video info: {:?}", video_info);
-                assert_eq!(video_info.url, test_url);
-                assert!(!video_info.title.is_empty());
-                assert!(!video_info.id.is_empty());
-            }
-            Err(e) => {
-                println!(
-                    "Warning: Failed to get video info (might be due to network/rate limiting): {}",
-                    e
-                );
-            }
+    #[test]
+    fn test_output_path_confinement() {
+        use crate::filename::sanitize_file_name;
+        use crate::security::resolve_output_dir;
+
+        let root = std::env::temp_dir().join(format!("snapper-roots-{}", uuid::Uuid::new_v4()));
+        let approved = root.join("Downloads");
+        std::fs::create_dir_all(&approved).expect("Failed to create test root");
+        let roots = [approved.clone()];
+
+        let nested = approved.join("Music").join("New");
+        let resolved = resolve_output_dir(&nested.to_string_lossy(), &roots)
+            .expect("Nested folder should be approved");
+        assert!(resolved.ends_with("Downloads/Music/New"));
+
+        let escape = format!("{}/../Elsewhere", approved.display());
+        assert!(resolve_output_dir(&escape, &roots).is_err());
+        assert!(resolve_output_dir(&root.join("Elsewhere").to_string_lossy(), &roots).is_err());
+        assert!(resolve_output_dir("Downloads", &roots).is_err());
+
+        #[cfg(unix)]
+        {
+            let link = approved.join("link");
+            std::os::unix::fs::symlink(&root, &link).expect("Failed to create symlink");
+            assert!(resolve_output_dir(&link.join("x").to_string_lossy(), &roots).is_err());
         }
+
+        assert_eq!(sanitize_file_name("../../etc/passwd", false), ".._.._etc_passwd");
+        assert_eq!(sanitize_file_name("..", false), "download");
+        assert_eq!(sanitize_file_name("--exec rm", false), "exec rm");
+
+        std::fs::remove_dir_all(&root).expect("Failed to clean up test root");
+    }
+
+    #[test]
+    fn test_supported_sites_lookup() {
+        use crate::sites::{default_sites, find_site, find_site_for_url};
+
+        let sites = default_sites();
+        assert_eq!(find_site(&sites, "Youtube").map(|s| s.name.as_str()), Some("YouTube"));
+        assert_eq!(find_site(&sites, "youtube:tab").map(|s| s.name.as_str()), Some("YouTube"));
+        assert_eq!(find_site(&sites, "TwitchVod").map(|s| s.name.as_str()), Some("Twitch VODs"));
+        assert!(find_site(&sites, "Generic").is_none());
+
+        let soundcloud = find_site(&sites, "Soundcloud").expect("SoundCloud should be listed");
+        assert_eq!(soundcloud.default_format.as_deref(), Some("mp3"));
+        assert!(soundcloud.referer.is_none());
+
+        let configured: Vec<crate::sites::SiteConfig> = serde_json::from_value(serde_json::json!([
+            { "name": "Vimeo", "extractors": ["Vimeo"], "enabled": false }
+        ]))
+        .expect("Failed to parse site settings");
+        assert!(!find_site(&configured, "vimeo").unwrap().enabled);
+
+        let site_for = |url| find_site_for_url(&sites, url).map(|s| s.name.as_str());
+        assert_eq!(site_for("https://vimeo.com/12345"), Some("Vimeo"));
+        assert_eq!(site_for("https://artist.bandcamp.com/track/song"), Some("Bandcamp"));
+        assert_eq!(site_for("https://www.twitch.tv/videos/1"), Some("Twitch VODs"));
+        assert_eq!(site_for("https://notvimeo.com/12345"), None);
+        assert_eq!(site_for("ftp://vimeo.com/12345"), None);
+
+        let custom: Vec<crate::sites::SiteConfig> = serde_json::from_value(serde_json::json!([
+            { "name": "Archive", "extractors": ["Generic"], "hosts": ["media.example.org"] }
+        ]))
+        .expect("Failed to parse site settings");
+        assert!(find_site_for_url(&custom, "https://cdn.media.example.org/v/1").is_some());
+        assert!(find_site_for_url(&custom, "https://example.org/v/1").is_none());
+    }
+
+    #[test]
+    fn test_analyze_url() {
+        use crate::urls::{analyze_url, parse_start_time, UrlKind};
+
+        let analysis = analyze_url(
+            "https://youtu.be/dQw4w9WgXcQ?si=tracking123&t=1m30s&utm_source=share",
+        )
+        .expect("Failed to analyze short link");
+        assert_eq!(analysis.kind, UrlKind::Video);
+        assert_eq!(analysis.video_id.as_deref(), Some("dQw4w9WgXcQ"));
+        assert_eq!(analysis.start_time, Some(90));
+        assert_eq!(
+            analysis.canonical_url,
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=90s"
+        );
+
+        let analysis = analyze_url(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL590L5WQmH8fJ54F369BLDSqIwcs-TCfs&pp=abc",
+        )
+        .unwrap();
+        assert_eq!(analysis.kind, UrlKind::Video);
+        assert_eq!(
+            analysis.playlist_id.as_deref(),
+            Some("PL590L5WQmH8fJ54F369BLDSqIwcs-TCfs")
+        );
+        assert!(!analysis.canonical_url.contains("pp="));
+
+        let kinds = [
+            ("https://youtube.com/shorts/xyz123", UrlKind::Short),
+            ("https://www.youtube.com/live/dQw4w9WgXcQ?feature=share", UrlKind::Live),
+            ("https://www.youtube.com/playlist?list=PLabc", UrlKind::Playlist),
+            ("https://www.youtube.com/@SomeChannel/videos", UrlKind::Channel),
+            ("https://www.youtube.com/channel/UC123", UrlKind::Channel),
+            ("https://music.youtube.com/watch?v=dQw4w9WgXcQ", UrlKind::MusicTrack),
+            ("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ", UrlKind::Video),
+            ("www.youtube.com/watch?v=dQw4w9WgXcQ", UrlKind::Video),
+        ];
+        for (url, kind) in kinds {
+            assert_eq!(analyze_url(url).map(|a| a.kind), Ok(kind), "{}", url);
+        }
+
+        assert!(analyze_url("ftp://youtube.com/watch?v=dQw4w9WgXcQ").is_err());
+        assert!(analyze_url("https://www.youtube.com/watch").is_err());
+        assert!(analyze_url("https://www.youtube.com/feed/trending").is_err());
+
+        assert_eq!(parse_start_time("1h2m3s"), Some(3723));
+        assert_eq!(parse_start_time("45"), Some(45));
+        assert_eq!(parse_start_time("abc"), None);
+        assert_eq!(parse_start_time("99999999999999999h"), None);
+        assert_eq!(parse_start_time("18446744073709551615s1s"), None);
     }
 
     #[test]
     fn test_supported_formats() {
-        let formats = vec!["mp4".to_string(), "mp3".to_string(), "wav".to_string(), "webm".to_string()];
+        let formats = ["mp4".to_string(), "mp3".to_string(), "wav".to_string(), "webm".to_string()];
         assert!(formats.contains(&"mp4".to_string()));
         assert!(formats.contains(&"mp3".to_string()));
         assert!(formats.contains(&"wav".to_string()));
@@ -89,7 +244,7 @@ mod tests {
 
     #[test]
     fn test_supported_qualities() {
-        let qualities = vec![
+        let qualities = [
             "best".to_string(),
             "high".to_string(),
             "medium".to_string(),
@@ -100,6 +255,1511 @@ mod tests {
         assert!(qualities.contains(&"high".to_string()));
     }
 
+    #[test]
+    fn test_postprocess_pipeline_deserialize() {
+        use crate::postprocess::{PostProcessPipeline, PostProcessStep};
+
+        let pipeline: PostProcessPipeline = serde_json::from_value(serde_json::json!({
+            "name": "podcast",
+            "steps": [
+                { "type": "trim_silence" },
+                { "type": "scale", "width": 1280, "height": -2 },
+                { "type": "strip_metadata" },
+                { "type": "convert", "format": "mkv" }
+            ]
+        }))
+        .expect("Failed to parse pipeline");
+
+        assert_eq!(pipeline.steps.len(), 4);
+        assert!(matches!(pipeline.steps[0], PostProcessStep::TrimSilence { .. }));
+        assert_eq!(
+            pipeline.steps[1].ffmpeg_args(),
+            vec!["-vf", "scale=1280:-2", "-c:a", "copy"]
+        );
+        assert_eq!(pipeline.steps[3].name(), "convert");
+    }
+
+    #[test]
+    fn test_postprocess_step_ffmpeg_args() {
+        use crate::postprocess::PostProcessStep;
+
+        let cases = vec![
+            (
+                PostProcessStep::TrimSilence {
+                    threshold_db: -40.0,
+                    min_duration: 0.25,
+                },
+                vec![
+                    "-af",
+                    "silenceremove=start_periods=1:start_threshold=-40dB:start_duration=0.25,areverse,\
+                     silenceremove=start_periods=1:start_threshold=-40dB:start_duration=0.25,areverse",
+                    "-c:v",
+                    "copy",
+                ],
+            ),
+            (
+                PostProcessStep::Crop {
+                    width: 640,
+                    height: 360,
+                    x: 10,
+                    y: 20,
+                },
+                vec!["-vf", "crop=640:360:10:20", "-c:a", "copy"],
+            ),
+            (
+                PostProcessStep::Scale {
+                    width: 1280,
+                    height: -2,
+                },
+                vec!["-vf", "scale=1280:-2", "-c:a", "copy"],
+            ),
+            (
+                PostProcessStep::ChangeFps { fps: 29.97 },
+                vec!["-vf", "fps=29.97", "-c:a", "copy"],
+            ),
+            (
+                PostProcessStep::StripMetadata,
+                vec!["-map", "0", "-map_metadata", "-1", "-map_chapters", "-1", "-c", "copy"],
+            ),
+            (
+                PostProcessStep::Convert {
+                    format: "mkv".to_string(),
+                },
+                vec![],
+            ),
+        ];
+
+        for (step, expected) in cases {
+            assert_eq!(step.ffmpeg_args(), expected, "arguments for {}", step.name());
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hooks_receive_title_without_shell() {
+        use crate::hooks::{run_hooks, HookConfig, HookEvent};
+
+        let history = DownloadHistory {
+            id: "hook-test".to_string(),
+            title: "Song $(touch /tmp/snapper-pwned); echo".to_string(),
+            url: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+            status: DownloadStatus::Completed,
+            downloaded_at: chrono::Utc::now(),
+            file_path: Some("/tmp/song.mp3".to_string()),
+            format: "mp3".to_string(),
+            quality: "high".to_string(),
+            output_files: vec!["/tmp/song.mp3".to_string()],
+            hook_results: Vec::new(),
+            extractor: Some("Youtube".to_string()),
+            ..Default::default()
+        };
+        let hooks = vec![HookConfig {
+            name: "printenv".to_string(),
+            program: "printenv".to_string(),
+            args: vec!["SNAPPER_TITLE".to_string()],
+            events: vec![HookEvent::Completed],
+            timeout_secs: 5,
+            enabled: true,
+        }];
+
+        let results = run_hooks(&hooks, &history).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].exit_code, Some(0));
+        assert_eq!(results[0].stdout.trim_end(), history.title);
+        assert!(!std::path::Path::new("/tmp/snapper-pwned").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hook_timeout_covers_payload_write() {
+        use crate::hooks::{run_hooks, HookConfig, HookEvent};
+
+        // Far more than a pipe buffer, sent to a hook that never reads it.
+        let history = DownloadHistory {
+            id: "hook-timeout".to_string(),
+            output_files: vec!["x".repeat(1 << 20)],
+            status: DownloadStatus::Completed,
+            ..Default::default()
+        };
+        let hooks = vec![HookConfig {
+            name: "sleep".to_string(),
+            program: "sleep".to_string(),
+            args: vec!["30".to_string()],
+            events: vec![HookEvent::Completed],
+            timeout_secs: 1,
+            enabled: true,
+        }];
+
+        let started = std::time::Instant::now();
+        let results = run_hooks(&hooks, &history).await;
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert!(results[0].timed_out);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery_to_local_listener() {
+        use crate::webhooks::{deliver, sign_payload, WebhookConfig, WebhookEvent, SIGNATURE_HEADER};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|l| {
+                            l.to_ascii_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+            socket
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let webhook = WebhookConfig {
+            name: "local".to_string(),
+            url: format!("http://{}/hook", address),
+            events: vec![WebhookEvent::Completed],
+            secret: Some("shared-secret".to_string()),
+            enabled: true,
+            max_retries: 0,
+        };
+        let body = br#"{"event":"Completed"}"#;
+        assert!(!format!("{:?}", webhook).contains("shared-secret"));
+
+        deliver(&webhook, WebhookEvent::Completed, body)
+            .await
+            .expect("Webhook delivery should succeed");
+
+        let request = server.await.unwrap();
+        let expected = format!(
+            "{}: {}",
+            SIGNATURE_HEADER.to_ascii_lowercase(),
+            sign_payload("shared-secret", body)
+        );
+        assert!(request.starts_with("POST /hook"));
+        assert!(request.to_ascii_lowercase().contains(&expected));
+        assert!(request.ends_with(r#"{"event":"Completed"}"#));
+    }
+
+    #[test]
+    fn test_webhook_payload_round_trip() {
+        use crate::webhooks::{WebhookEvent, WebhookPayload};
+
+        let failure = DownloadFailure::from_message("ERROR: [youtube] abc: Private video");
+        let payload = WebhookPayload {
+            event: WebhookEvent::Failed,
+            sent_at: chrono::Utc::now(),
+            download: DownloadHistory {
+                id: "abc".to_string(),
+                title: "Video".to_string(),
+                url: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+                status: DownloadStatus::Failed,
+                error: Some(failure.clone()),
+                ..Default::default()
+            },
+            error_message: Some(failure.message.clone()),
+        };
+
+        let body = serde_json::to_string(&payload).unwrap();
+        assert_eq!(body.matches(r#""error":"#).count(), 1);
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["id"], "abc");
+        assert_eq!(value["error"]["category"], "restricted");
+        assert_eq!(value["error_message"], failure.message.as_str());
+
+        let parsed: WebhookPayload = serde_json::from_str(&body).unwrap();
+        assert_eq!(parsed.event, WebhookEvent::Failed);
+        assert_eq!(parsed.download.id, "abc");
+        assert_eq!(parsed.download.error.unwrap().category, ErrorCategory::Restricted);
+        assert_eq!(parsed.error_message, Some(failure.message));
+    }
+
+    #[tokio::test]
+    async fn test_direct_download_resumes_against_local_server() {
+        use crate::backend::CancellationToken;
+        use crate::direct::{is_public_ip, DirectDownloader};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Mutex};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let body: Arc<Vec<u8>> = Arc::new((0..200_000u32).map(|i| (i % 251) as u8).collect());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let full_gets = Arc::new(AtomicUsize::new(0));
+
+        let server_body = body.clone();
+        let server_gets = full_gets.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                let len = server_body.len();
+
+                if request.starts_with("head /named") {
+                    let response = "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Disposition: attachment; filename=\"fallback.bin\"; filename*=UTF-8''na%C3%AFve%20clip.mp4\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                } else if request.starts_with("head /page.html") {
+                    let response = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                } else if request.starts_with("head") {
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: video/mp4\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+                        len
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                } else if let Some(start) = request
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok())
+                {
+                    let response = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Type: video/mp4\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                        len - start,
+                        start,
+                        len - 1,
+                        len
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                    socket.write_all(&server_body[start..]).await.unwrap();
+                } else {
+                    // The first full request is cut off half way to force a resume.
+                    server_gets.fetch_add(1, Ordering::SeqCst);
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: video/mp4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        len
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                    socket.write_all(&server_body[..len / 4]).await.unwrap();
+                }
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = events.clone();
+        let public = DirectDownloader::new().unwrap();
+        assert!(public.probe(&format!("http://{}/media/clip.mp4", address)).await.is_err());
+        assert!(public
+            .probe(&format!("http://localhost:{}/media/clip.mp4", address.port()))
+            .await
+            .is_err());
+        assert!(public.probe("file:///etc/passwd").await.is_err());
+        assert!(is_public_ip("93.184.216.34".parse().unwrap()));
+        for private in ["10.0.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "::1", "fd00::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public_ip(private.parse().unwrap()), "{} should not be public", private);
+        }
+
+        let downloader = DirectDownloader::allowing_local_hosts()
+            .unwrap()
+            .with_progress_callback(Some(Arc::new(move |p: DownloadProgress| {
+                recorder.lock().unwrap().push(p.progress);
+            })));
+
+        let page = downloader
+            .probe(&format!("http://{}/page.html", address))
+            .await
+            .expect("Probe of HTML page failed");
+        assert!(page.is_none());
+
+        let media = downloader
+            .probe(&format!("http://{}/media/clip.mp4?token=abc", address))
+            .await
+            .expect("Probe failed")
+            .expect("Media URL should be detected");
+        assert_eq!(media.extension, "mp4");
+        assert_eq!(media.content_length, Some(body.len() as u64));
+        assert!(media.accepts_ranges);
+        assert_eq!(media.title(), "clip");
+
+        let named = downloader
+            .probe(&format!("http://{}/named", address))
+            .await
+            .expect("Probe failed")
+            .expect("Named download should be detected");
+        assert_eq!(named.file_name.as_deref(), Some("naïve clip.mp4"));
+        assert_eq!(named.extension, "mp4");
+
+        let dir = std::env::temp_dir().join(format!("snapper-direct-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let destination = dir.join("clip.mp4");
+        let partial = dir.join("clip.mp4.part");
+        let size = downloader
+            .download(&media, &partial, &destination, "direct-test", &CancellationToken::new())
+            .await
+            .expect("Direct download failed");
+
+        assert_eq!(size, body.len() as u64);
+        assert_eq!(std::fs::read(&destination).unwrap(), *body);
+        assert!(!partial.exists());
+        assert_eq!(full_gets.load(Ordering::SeqCst), 1, "download should resume with Range");
+        assert_eq!(events.lock().unwrap().last().copied(), Some(100.0));
+
+        // A partial file left by an earlier attempt is picked up where it ended.
+        std::fs::write(&partial, &body[..body.len() / 2]).unwrap();
+        let retried = dir.join("retried.mp4");
+        downloader
+            .download(&media, &partial, &retried, "direct-retry", &CancellationToken::new())
+            .await
+            .expect("Resumed download failed");
+        assert_eq!(std::fs::read(&retried).unwrap(), *body);
+        assert_eq!(full_gets.load(Ordering::SeqCst), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scripted_backend() {
+        use crate::backend::{BackendOptions, CancellationToken, MediaBackend};
+        use crate::scripted::ScriptedBackend;
+        use std::sync::{Arc, Mutex};
+
+        let dir = std::env::temp_dir().join(format!("snapper-scripted-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = serde_json::json!({
+            "videos": {
+                "*": {
+                    "id": "dQw4w9WgXcQ",
+                    "title": "Scripted Video",
+                    "url": "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                    "duration": 212,
+                    "thumbnail": null,
+                    "uploader": "Scripted Channel",
+                    "upload_date": null,
+                    "view_count": null,
+                    "playlist_title": null,
+                    "playlist_index": null,
+                    "available_formats": ["mp4"]
+                }
+            },
+            "formats": [{ "format_id": "22", "ext": "mp4", "height": 720 }],
+            "progress": [25.0, 50.0, 75.0]
+        });
+        let script_path = dir.join("script.json");
+        std::fs::write(&script_path, script.to_string()).unwrap();
+        let script_file = script_path.to_string_lossy().to_string();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = events.clone();
+        let mut options = BackendOptions::new(dir.join("out"));
+        options.progress_callback = Some(Arc::new(move |p: DownloadProgress| {
+            recorder.lock().unwrap().push(p.progress);
+        }));
+        let backend = ScriptedBackend::from_file(&script_file, options.clone())
+            .expect("Scripted backend failed to load");
+        assert_eq!(backend.name(), "scripted");
+
+        let url = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+        let info = backend.probe(url, false).await.unwrap();
+        assert_eq!(info.title, "Scripted Video");
+        assert_eq!(backend.list_formats(url).await.unwrap()[0].format_id, "22");
+        assert_eq!(backend.identify_site(url, false).await.unwrap().name, "YouTube");
+
+        let request = DownloadRequest {
+            url: url.to_string(),
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            output_path: dir.join("out").to_string_lossy().to_string(),
+            filename: None,
+            pipeline: None,
+            collision_policy: None,
+        };
+        let history = backend
+            .download(request.clone(), "scripted-1".to_string(), CancellationToken::new())
+            .await
+            .unwrap();
+        assert!(matches!(history.status, DownloadStatus::Completed));
+        let file = PathBuf::from(history.file_path.expect("Completed download has a file"));
+        assert_eq!(std::fs::read(&file).unwrap(), b"scripted download");
+        assert_eq!(*events.lock().unwrap(), vec![25.0, 50.0, 75.0, 100.0]);
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let cancelled = backend
+            .download(request.clone(), "scripted-2".to_string(), cancel)
+            .await
+            .unwrap();
+        assert!(matches!(cancelled.status, DownloadStatus::Cancelled));
+
+        let mut failing = script.clone();
+        failing["outcome"] = serde_json::json!({ "type": "fail", "message": "scripted failure" });
+        std::fs::write(&script_path, failing.to_string()).unwrap();
+        let backend = ScriptedBackend::from_file(&script_file, options).unwrap();
+        let error = backend
+            .download(request, "scripted-3".to_string(), CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "scripted failure");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_complete_download_with_scripted_backend() {
+        use crate::backend::{BackendOptions, CancellationToken, MediaBackend};
+        use crate::commands::{complete_download, AppSettings};
+        use crate::postprocess::{PostProcessPipeline, PostProcessStepStatus};
+        use crate::scripted::{BackendScript, ScriptedBackend, ScriptedOutcome};
+        use chrono::Utc;
+        use std::sync::{Arc, Mutex};
+
+        let dir = std::env::temp_dir().join(format!("snapper-complete-{}", uuid::Uuid::new_v4()));
+        let url = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+        let script: BackendScript = serde_json::from_value(serde_json::json!({
+            "videos": {
+                "*": {
+                    "id": "dQw4w9WgXcQ",
+                    "title": "Scripted Video",
+                    "url": url,
+                    "duration": 212,
+                    "thumbnail": null,
+                    "uploader": "Scripted Channel",
+                    "upload_date": null,
+                    "view_count": null,
+                    "playlist_title": null,
+                    "playlist_index": null,
+                    "available_formats": ["mp4"]
+                }
+            },
+            "progress": [50.0]
+        }))
+        .unwrap();
+        let settings = AppSettings::default();
+        let request = DownloadRequest {
+            url: url.to_string(),
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            output_path: dir.to_string_lossy().to_string(),
+            filename: None,
+            pipeline: None,
+            collision_policy: None,
+        };
+        let started = |id: &str| DownloadHistory {
+            id: id.to_string(),
+            title: url.to_string(),
+            url: url.to_string(),
+            status: DownloadStatus::Downloading,
+            started_at: Some(Utc::now()),
+            attempts: 2,
+            ..Default::default()
+        };
+        let prepare_with = |script: BackendScript, pipeline: Option<PostProcessPipeline>| {
+            let backend: Arc<dyn MediaBackend> =
+                Arc::new(ScriptedBackend::new(script, BackendOptions::new(dir.clone())));
+            Ok((pipeline, backend))
+        };
+        let prepare = |script: BackendScript| prepare_with(script, None);
+
+        let completed = complete_download(
+            prepare(script.clone()),
+            request.clone(),
+            started("job-1"),
+            &settings,
+            CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        assert!(matches!(completed.status, DownloadStatus::Completed));
+        assert_eq!(completed.id, "job-1");
+        assert_eq!(completed.title, "Scripted Video");
+        assert_eq!(completed.attempts, 2);
+        assert!(completed.started_at.is_some());
+        assert!(completed.finished_at.is_some());
+        assert!(PathBuf::from(completed.file_path.unwrap()).exists());
+        assert!(completed.error.is_none());
+
+        // The scripted backend has no ffmpeg, so the pipeline fails but the
+        // download is kept, with the failure on the entry and in the event.
+        let pipeline: PostProcessPipeline = serde_json::from_value(serde_json::json!({
+            "name": "smaller",
+            "steps": [{ "type": "scale", "width": 640, "height": -2 }]
+        }))
+        .unwrap();
+        let events = Mutex::new(Vec::new());
+        let processed = complete_download(
+            prepare_with(script.clone(), Some(pipeline)),
+            request.clone(),
+            started("job-pp"),
+            &settings,
+            CancellationToken::new(),
+            |progress| events.lock().unwrap().push(progress),
+        )
+        .await;
+        assert!(matches!(processed.status, DownloadStatus::Completed));
+        assert!(PathBuf::from(processed.file_path.unwrap()).exists());
+        let failure = processed.error.expect("Post-processing failure is recorded");
+        assert_eq!(failure.category, ErrorCategory::PostProcessing);
+        assert!(failure.message.contains("'smaller'"), "{}", failure.message);
+        let events = events.into_inner().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0].status, PostProcessStepStatus::Failed));
+        assert_eq!(events[0].step, "scale");
+        assert!(events[0].error.is_some());
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let cancelled = complete_download(
+            prepare(script.clone()),
+            request.clone(),
+            started("job-2"),
+            &settings,
+            cancel,
+            |_| {},
+        )
+        .await;
+        assert!(matches!(cancelled.status, DownloadStatus::Cancelled));
+        assert!(cancelled.error.is_none());
+
+        let mut failing = script;
+        failing.outcome = ScriptedOutcome::Fail {
+            message: "scripted failure".to_string(),
+        };
+        let failed = complete_download(
+            prepare(failing),
+            request.clone(),
+            started("job-3"),
+            &settings,
+            CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        assert!(matches!(failed.status, DownloadStatus::Failed));
+        assert_eq!(failed.title, url);
+        assert_eq!(failed.attempts, 2);
+        assert!(failed.finished_at.is_some());
+        assert_eq!(failed.error.unwrap().message, "scripted failure");
+
+        // A backend that could not be prepared fails the entry with the reason.
+        let unprepared = complete_download(
+            Err("Unknown post-processing pipeline: missing".to_string()),
+            request,
+            started("job-4"),
+            &settings,
+            CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        assert!(matches!(unprepared.status, DownloadStatus::Failed));
+        assert_eq!(
+            unprepared.error.unwrap().message,
+            "Unknown post-processing pipeline: missing"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fixture_record_and_replay() {
+        use crate::fixtures::{load_fixture, record_to, replay_from};
+        use std::os::unix::process::ExitStatusExt;
+
+        let root = std::env::temp_dir().join(format!("snapper-fixtures-{}", uuid::Uuid::new_v4()));
+        let fixtures_dir = root.join("fixtures");
+        let args_for = |run: &str| -> Vec<String> {
+            let home = root.join(run).join("home");
+            let temp = root.join(run).join("temp");
+            vec![
+                "--paths".to_string(),
+                format!("home:{}", home.display()),
+                "--paths".to_string(),
+                format!("temp:{}", temp.display()),
+                "--print-to-file".to_string(),
+                "after_move:SNAPPER_FILEPATH %(filepath)s".to_string(),
+                temp.join("output-report.txt").to_string_lossy().to_string(),
+                "--".to_string(),
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+            ]
+        };
+
+        let recorded = args_for("record");
+        let recorded_home = root.join("record").join("home");
+        std::fs::create_dir_all(root.join("record").join("temp")).unwrap();
+        std::fs::write(
+            root.join("record").join("temp").join("output-report.txt"),
+            format!("SNAPPER_FILEPATH {}\n", recorded_home.join("Video.mp4").display()),
+        )
+        .unwrap();
+        let output = std::process::Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: format!("[download] Destination: {}\n", recorded_home.join("Video.mp4").display()).into_bytes(),
+            stderr: b"WARNING: recorded\n".to_vec(),
+        };
+        record_to(&fixtures_dir, &recorded, &output).unwrap();
+
+        let fixture = load_fixture(&fixtures_dir, &recorded).unwrap();
+        assert_eq!(fixture.args[1], "home:{home}");
+        assert_eq!(fixture.files, vec!["{home}/Video.mp4".to_string()]);
+        assert!(!fixture.stdout.contains(&root.to_string_lossy().to_string()));
+
+        let replayed = args_for("replay");
+        let replay_home = root.join("replay").join("home");
+        std::fs::create_dir_all(root.join("replay").join("temp")).unwrap();
+        let (stdout, stderr, exit_code) = replay_from(&fixtures_dir, &replayed).unwrap();
+        assert_eq!(exit_code, 0);
+        assert_eq!(stderr, "WARNING: recorded\n");
+        assert!(stdout.contains(&replay_home.join("Video.mp4").to_string_lossy().to_string()));
+        assert!(replay_home.join("Video.mp4").exists());
+
+        let report =
+            std::fs::read_to_string(root.join("replay").join("temp").join("output-report.txt")).unwrap();
+        let parsed = parse_output_report(&report).unwrap();
+        assert_eq!(parsed.file_path, replay_home.join("Video.mp4"));
+
+        let mut unknown = replayed.clone();
+        unknown[8] = "https://www.youtube.com/watch?v=unrecorded0".to_string();
+        assert!(replay_from(&fixtures_dir, &unknown).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_download_manager_queue() {
+        use crate::manager::{Cancellation, DownloadManager};
+
+        let request = |n: u32| DownloadRequest {
+            url: format!("https://www.youtube.com/watch?v=video{:06}", n),
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            output_path: "/tmp".to_string(),
+            filename: None,
+            pipeline: None,
+            collision_policy: None,
+        };
+
+        let manager = DownloadManager::new();
+        for n in 1..=4 {
+            manager.enqueue(&format!("job-{}", n), request(n));
+        }
+
+        let (first, first_token) = manager.start_next(2).unwrap();
+        let (second, _) = manager.start_next(2).unwrap();
+        assert_eq!((first.id.as_str(), second.id.as_str()), ("job-1", "job-2"));
+        assert!(manager.start_next(2).is_none(), "limit of two running jobs");
+        assert_eq!(manager.active_count(), 2);
+        assert!(manager.has_job("job-1") && manager.has_job("job-3"));
+        assert!(!manager.has_job("missing"));
+
+        assert!(matches!(manager.cancel("job-1"), Cancellation::Running));
+        assert!(first_token.is_cancelled());
+        match manager.cancel("job-3") {
+            Cancellation::Dequeued(job) => assert_eq!(job.request.url, request(3).url),
+            other => panic!("expected queued job to be removed, got {:?}", other),
+        }
+        assert!(matches!(manager.cancel("missing"), Cancellation::NotFound));
+
+        manager.finish("job-1");
+        let (next, _) = manager.start_next(2).unwrap();
+        assert_eq!(next.id, "job-4");
+
+        let snapshot = manager.snapshot(2);
+        assert_eq!(snapshot.active.len(), 2);
+        assert!(snapshot.queued.is_empty());
+    }
+
+    #[test]
+    fn test_history_change_payload() {
+        use crate::commands::HistoryChange;
+
+        let entry = DownloadHistory {
+            id: "abc".to_string(),
+            title: "Video".to_string(),
+            url: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+            status: DownloadStatus::Completed,
+            downloaded_at: chrono::Utc::now(),
+            file_path: None,
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            output_files: Vec::new(),
+            hook_results: Vec::new(),
+            extractor: None,
+            ..Default::default()
+        };
+        let updated = serde_json::to_value(HistoryChange::Updated {
+            entry: Box::new(entry),
+        })
+        .unwrap();
+        assert_eq!(updated["kind"], "updated");
+        assert_eq!(updated["entry"]["id"], "abc");
+        assert_eq!(updated["entry"]["status"], "Completed");
+
+        let cleared = serde_json::to_value(HistoryChange::Cleared).unwrap();
+        assert_eq!(cleared, serde_json::json!({ "kind": "cleared" }));
+    }
+
+    #[test]
+    fn test_history_store() {
+        use crate::history::{HistoryStore, RetentionPolicy};
+        use chrono::{Duration, Utc};
+
+        let entry = |id: &str, url: &str, status: DownloadStatus, age_days: i64| DownloadHistory {
+            id: id.to_string(),
+            title: format!("Video {}", id),
+            url: url.to_string(),
+            status,
+            downloaded_at: Utc::now() - Duration::days(age_days),
+            file_path: None,
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            output_files: Vec::new(),
+            hook_results: Vec::new(),
+            extractor: None,
+            ..Default::default()
+        };
+
+        let store = HistoryStore::open_in_memory().unwrap();
+        store
+            .upsert(&entry("a", "https://youtu.be/dQw4w9WgXcQ", DownloadStatus::Completed, 30))
+            .unwrap();
+        store
+            .upsert(&entry("b", "https://vimeo.com/1", DownloadStatus::Failed, 10))
+            .unwrap();
+        store
+            .upsert(&entry("c", "https://vimeo.com/1", DownloadStatus::Downloading, 40))
+            .unwrap();
+
+        let ids: Vec<String> = store.list().unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec!["b", "a", "c"]);
+        assert_eq!(store.find_by_url("https://vimeo.com/1").unwrap().len(), 2);
+        assert_eq!(store.find_by_video_id("dQw4w9WgXcQ").unwrap()[0].id, "a");
+        let active: Vec<String> = store.find_active().unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(active, vec!["c"]);
+        store
+            .update("a", |e| e.file_path = Some("/videos/a.mp4".to_string()))
+            .unwrap();
+        assert_eq!(store.find_by_file_path("/videos/a.mp4").unwrap()[0].id, "a");
+        assert!(store.find_by_file_path("/videos/b.mp4").unwrap().is_empty());
+
+        let updated = store
+            .update("b", |e| e.status = DownloadStatus::Cancelled)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(updated.status, DownloadStatus::Cancelled));
+        assert!(matches!(store.get("b").unwrap().unwrap().status, DownloadStatus::Cancelled));
+        assert!(store.update("missing", |_| {}).unwrap().is_none());
+
+        let legacy = vec![
+            entry("a", "https://example.com/changed", DownloadStatus::Failed, 0),
+            entry("d", "https://vimeo.com/2", DownloadStatus::Completed, 400),
+        ];
+        assert_eq!(store.import(&legacy).unwrap(), 1);
+        assert_eq!(store.get("a").unwrap().unwrap().url, "https://youtu.be/dQw4w9WgXcQ");
+
+        // The 40-day-old entry is still downloading and must survive pruning.
+        assert_eq!(store.apply_retention(RetentionPolicy::MaxAgeDays { days: 35 }).unwrap(), 1);
+        assert!(store.get("d").unwrap().is_none());
+        assert!(store.get("c").unwrap().is_some());
+
+        assert_eq!(store.apply_retention(RetentionPolicy::MaxEntries { count: 1 }).unwrap(), 1);
+        let ids: Vec<String> = store.list().unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec!["b", "c"]);
+        assert_eq!(store.apply_retention(RetentionPolicy::Unlimited).unwrap(), 0);
+
+        assert_eq!(store.delete(&["b".to_string(), "missing".to_string()]).unwrap(), 1);
+        assert_eq!(store.clear().unwrap(), 1);
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_query_history() {
+        use crate::history::{HistoryQuery, HistorySort, HistoryStore, SortOrder};
+        use chrono::{Duration, Utc};
+
+        let store = HistoryStore::open_in_memory().unwrap();
+        let now = Utc::now();
+        for n in 0..30 {
+            store
+                .upsert(&DownloadHistory {
+                    id: format!("id-{:02}", n),
+                    title: if n % 10 == 0 { format!("Live 100% set {}", n) } else { format!("Track {}", n) },
+                    url: format!("https://vimeo.com/{}", n),
+                    status: if n % 3 == 0 { DownloadStatus::Failed } else { DownloadStatus::Completed },
+                    downloaded_at: now - Duration::hours(n),
+                    file_path: None,
+                    format: if n % 2 == 0 { "mp4" } else { "mp3" }.to_string(),
+                    quality: "high".to_string(),
+                    output_files: Vec::new(),
+                    hook_results: Vec::new(),
+                    extractor: Some(if n < 5 { "Youtube" } else { "Vimeo" }.to_string()),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        let first = store.query(&HistoryQuery { limit: Some(10), ..Default::default() }).unwrap();
+        assert_eq!((first.total, first.unfiltered_total, first.entries.len()), (30, 30, 10));
+        assert_eq!(first.entries[0].id, "id-00");
+        let last = store
+            .query(&HistoryQuery { limit: Some(10), offset: 25, ..Default::default() })
+            .unwrap();
+        assert_eq!(last.entries.len(), 5);
+        assert_eq!(last.entries[4].id, "id-29");
+
+        // `%` in the search text is literal, not a wildcard.
+        let search = store
+            .query(&HistoryQuery { search: Some("100%".to_string()), ..Default::default() })
+            .unwrap();
+        assert_eq!(search.total, 3);
+        let by_url = store
+            .query(&HistoryQuery { search: Some("vimeo.com/17".to_string()), ..Default::default() })
+            .unwrap();
+        assert_eq!(by_url.entries[0].id, "id-17");
+
+        let filtered = store
+            .query(&HistoryQuery {
+                statuses: vec![DownloadStatus::Failed],
+                formats: vec!["mp4".to_string()],
+                extractors: vec!["Vimeo".to_string()],
+                from: Some(now - Duration::hours(20)),
+                sort: HistorySort::DownloadedAt,
+                order: SortOrder::Ascending,
+                ..Default::default()
+            })
+            .unwrap();
+        let ids: Vec<&str> = filtered.entries.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["id-18", "id-12", "id-06"]);
+        assert_eq!(filtered.unfiltered_total, 30);
+
+        let by_title = store
+            .query(&HistoryQuery {
+                sort: HistorySort::Title,
+                order: SortOrder::Ascending,
+                limit: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_title.entries[0].title, "Live 100% set 0");
+
+        let query: HistoryQuery =
+            serde_json::from_value(serde_json::json!({ "statuses": ["Completed"], "sort": "title" })).unwrap();
+        assert_eq!(store.query(&query).unwrap().total, 20);
+    }
+
+    #[test]
+    fn test_download_statistics() {
+        use crate::history::HistoryStore;
+        use crate::stats::{series_csv, StatsInterval, StatsQuery};
+        use chrono::{Local, NaiveDate, TimeZone, Utc};
+
+        let at = |month: u32, day: u32| {
+            Local.with_ymd_and_hms(2025, month, day, 12, 0, 0).unwrap().with_timezone(&Utc)
+        };
+        let entry = |id: &str, status: DownloadStatus, downloaded_at, uploader: Option<&str>| DownloadHistory {
+            id: id.to_string(),
+            title: id.to_string(),
+            url: format!("https://vimeo.com/{}", id),
+            status,
+            downloaded_at,
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            uploader: uploader.map(|u| u.to_string()),
+            ..Default::default()
+        };
+
+        let store = HistoryStore::open_in_memory().unwrap();
+        let mut a = entry("a", DownloadStatus::Completed, at(3, 3), Some("Alice"));
+        (a.file_size, a.duration, a.average_speed) = (Some(1000), Some(60), Some(100.0));
+        let mut b = entry("b", DownloadStatus::Completed, at(3, 5), Some("Alice"));
+        (b.file_size, b.duration, b.average_speed) = (Some(3000), Some(120), Some(300.0));
+        b.format = "mp3".to_string();
+        let mut c = entry("c", DownloadStatus::Failed, at(3, 5), Some("Bob"));
+        c.error = Some(DownloadFailure::from_message("ERROR: Connection reset by peer"));
+        let d = entry("d", DownloadStatus::Failed, at(3, 12), None);
+        let mut e = entry("e", DownloadStatus::Completed, at(4, 1), Some("Bob"));
+        e.file_size = Some(500);
+        let mut outside = entry("outside", DownloadStatus::Completed, at(1, 1), Some("Carol"));
+        outside.file_size = Some(1 << 30);
+        for entry in [a, b, c, d, e, outside] {
+            store.upsert(&entry).unwrap();
+        }
+
+        let mut query = StatsQuery {
+            from: Some(Local.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap().with_timezone(&Utc)),
+            to: Some(Local.with_ymd_and_hms(2025, 4, 2, 23, 59, 59).unwrap().with_timezone(&Utc)),
+            interval: StatsInterval::Week,
+            top_uploaders: None,
+        };
+        let stats = store.statistics(&query).unwrap();
+        assert_eq!((stats.total, stats.completed, stats.failed), (5, 3, 2));
+        assert_eq!(stats.success_rate, Some(0.6));
+        assert_eq!(stats.total_bytes, 4500);
+        assert_eq!(stats.total_duration_seconds, 180);
+        assert_eq!(stats.average_speed, Some(200.0));
+        assert_eq!(stats.most_used_format.as_deref(), Some("mp4"));
+        assert_eq!(stats.formats_breakdown["mp3"], 1);
+        let uploaders: Vec<(&str, usize, u64)> = stats
+            .top_uploaders
+            .iter()
+            .map(|u| (u.uploader.as_str(), u.downloads, u.bytes))
+            .collect();
+        assert_eq!(uploaders, vec![("Alice", 2, 4000), ("Bob", 2, 500)]);
+        let failures: Vec<(ErrorCategory, usize)> =
+            stats.failure_reasons.iter().map(|f| (f.category, f.count)).collect();
+        assert_eq!(failures, vec![(ErrorCategory::Network, 1), (ErrorCategory::Other, 1)]);
+
+        // 1 March 2025 is a Saturday, so the first week starts on 24 February;
+        // weeks without downloads are filled in.
+        let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+        let weeks: Vec<_> = stats.series.iter().map(|p| (p.period, p.downloads, p.bytes)).collect();
+        assert_eq!(
+            weeks,
+            vec![
+                (date(2, 24), 0, 0),
+                (date(3, 3), 3, 4000),
+                (date(3, 10), 1, 0),
+                (date(3, 17), 0, 0),
+                (date(3, 24), 0, 0),
+                (date(3, 31), 1, 500),
+            ]
+        );
+
+        query.interval = StatsInterval::Month;
+        let stats = store.statistics(&query).unwrap();
+        assert_eq!(
+            series_csv(&stats),
+            "period,downloads,completed,failed,bytes,duration_seconds\n\
+             2025-03-01,4,2,2,4000,180\n\
+             2025-04-01,1,1,0,500,0\n"
+        );
+
+        let everything = store.statistics(&StatsQuery::default()).unwrap();
+        assert_eq!(everything.total, 6);
+        assert_eq!(everything.series.first().map(|p| p.period), Some(date(1, 1)));
+        assert_eq!(everything.series.last().map(|p| p.period), Some(date(4, 1)));
+
+        // Long ranges are cut from the oldest end, keeping the latest periods.
+        let days = StatsInterval::Day.periods(date(1, 1) - chrono::Duration::days(5000), date(4, 1));
+        assert_eq!(days.len(), crate::stats::MAX_SERIES_POINTS);
+        assert_eq!(days.last(), Some(&date(4, 1)));
+        assert_eq!(days.first(), Some(&(date(4, 1) - chrono::Duration::days(999))));
+    }
+
+    #[test]
+    fn test_move_to_trash_in() {
+        use crate::trash::move_to_trash_in;
+
+        let root = std::env::temp_dir().join(format!("snapper-trash-{}", uuid::Uuid::new_v4()));
+        let trash = root.join("Trash");
+        let downloads = root.join("My Downloads");
+        std::fs::create_dir_all(&downloads).unwrap();
+
+        let first = downloads.join("clip.mp4");
+        std::fs::write(&first, b"one").unwrap();
+        let trashed = move_to_trash_in(&first, &trash).unwrap();
+        assert!(!first.exists());
+        assert_eq!(trashed, trash.join("files").join("clip.mp4"));
+        let info = std::fs::read_to_string(trash.join("info").join("clip.mp4.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath="));
+        assert!(info.contains("My%20Downloads/clip.mp4"));
+        assert!(info.contains("DeletionDate="));
+
+        std::fs::write(&first, b"two").unwrap();
+        let second = move_to_trash_in(&first, &trash).unwrap();
+        assert_eq!(second, trash.join("files").join("clip (1).mp4"));
+        assert_eq!(std::fs::read(&second).unwrap(), b"two");
+        assert!(trash.join("info").join("clip (1).mp4.trashinfo").exists());
+
+        assert!(move_to_trash_in(&first, &trash).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_reconcile_history_with_filesystem() {
+        use crate::reconcile::{fingerprint, verify_entries, VerifyReport};
+
+        let root = std::env::temp_dir().join(format!("snapper-reconcile-{}", uuid::Uuid::new_v4()));
+        let archive = root.join("archive");
+        std::fs::create_dir_all(&archive).unwrap();
+        let entry = |id: &str, path: &std::path::Path| DownloadHistory {
+            id: id.to_string(),
+            title: id.to_string(),
+            url: format!("https://vimeo.com/{}", id),
+            status: DownloadStatus::Completed,
+            file_path: Some(path.to_string_lossy().to_string()),
+            output_files: vec![path.to_string_lossy().to_string()],
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            ..Default::default()
+        };
+
+        let present = root.join("present.mp4");
+        std::fs::write(&present, b"present").unwrap();
+
+        // Moved into a subfolder under the same name.
+        let moved = root.join("moved.mp4");
+        std::fs::write(archive.join("moved.mp4"), b"moved").unwrap();
+        let mut moved_entry = entry("moved", &moved);
+        moved_entry.file_size = Some(5);
+
+        // A same-named file is no proof without a recorded size.
+        std::fs::write(archive.join("unsized.mp4"), b"unsized").unwrap();
+
+        // Moved and renamed; only size and fingerprint can find it.
+        let renamed = root.join("renamed.mp4");
+        let body: Vec<u8> = (0..200_000u32).map(|i| (i % 249) as u8).collect();
+        std::fs::write(&renamed, &body).unwrap();
+        let mut renamed_entry = entry("renamed", &renamed);
+        renamed_entry.file_size = Some(body.len() as u64);
+        renamed_entry.file_hash = Some(fingerprint(&renamed).unwrap());
+        std::fs::rename(&renamed, archive.join("Renamed by user.mp4")).unwrap();
+        // Same size, different content: must not be mistaken for it.
+        let mut decoy = body.clone();
+        decoy[150_000] ^= 0xff;
+        std::fs::write(archive.join("decoy.mp4"), &decoy).unwrap();
+
+        let mut returned = entry("returned", &root.join("returned.mp4"));
+        returned.status = DownloadStatus::Missing;
+        std::fs::write(root.join("returned.mp4"), b"back").unwrap();
+
+        let mut failed = entry("failed", &root.join("failed.mp4"));
+        failed.status = DownloadStatus::Failed;
+
+        // Skipped entries keep their status, and with it delete protection,
+        // when their file is moved or goes missing and comes back.
+        let mut skipped_moved = entry("skipped-moved", &root.join("skipped-moved.mp4"));
+        skipped_moved.status = DownloadStatus::Skipped;
+        skipped_moved.file_size = Some(4);
+        std::fs::write(archive.join("skipped-moved.mp4"), b"skip").unwrap();
+        let mut skipped_gone = entry("skipped-gone", &root.join("skipped-gone.mp4"));
+        skipped_gone.status = DownloadStatus::Skipped;
+
+        let entries = vec![
+            entry("present", &present),
+            moved_entry,
+            entry("unsized", &root.join("unsized.mp4")),
+            renamed_entry,
+            entry("gone", &root.join("gone.mp4")),
+            returned,
+            failed,
+            skipped_moved,
+            skipped_gone,
+        ];
+        let (report, changed) = verify_entries(entries, std::slice::from_ref(&root));
+        assert_eq!(
+            report,
+            VerifyReport { checked: 8, present: 2, missing: 3, relocated: 3 }
+        );
+
+        let find = |id: &str| changed.iter().find(|e| e.id == id).unwrap().clone();
+        assert_eq!(find("present").file_size, Some(7));
+        assert!(find("present").file_hash.is_some());
+        let moved = find("moved");
+        assert_eq!(moved.file_path, Some(archive.join("moved.mp4").to_string_lossy().to_string()));
+        assert_eq!(moved.output_files, vec![moved.file_path.clone().unwrap()]);
+        assert_eq!(
+            find("renamed").file_path,
+            Some(archive.join("Renamed by user.mp4").to_string_lossy().to_string())
+        );
+        assert!(matches!(find("gone").status, DownloadStatus::Missing));
+        assert!(matches!(find("unsized").status, DownloadStatus::Missing));
+        assert!(matches!(find("returned").status, DownloadStatus::Completed));
+        assert!(!changed.iter().any(|e| e.id == "failed"));
+        assert!(matches!(find("skipped-moved").status, DownloadStatus::Skipped));
+        let skipped_gone = find("skipped-gone");
+        assert!(matches!(skipped_gone.status, DownloadStatus::Missing));
+        assert!(matches!(skipped_gone.status_before_missing, Some(DownloadStatus::Skipped)));
+
+        std::fs::write(root.join("skipped-gone.mp4"), b"found").unwrap();
+        let (report, changed) = verify_entries(vec![skipped_gone], std::slice::from_ref(&root));
+        assert_eq!(report.present, 1);
+        assert!(matches!(changed[0].status, DownloadStatus::Skipped));
+        assert!(changed[0].status_before_missing.is_none());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_staging_cleanup_only_touches_snapper_folders() {
+        use crate::staging;
+
+        let base = std::env::temp_dir().join(format!("snapper-staging-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&base).unwrap();
+        std::fs::write(base.join("notes.txt"), b"user file").unwrap();
+        let root = staging::staging_root_in(&base);
+
+        let leftover = staging::create_staging_dir(&root, "leftover").unwrap();
+        std::fs::write(leftover.join("video.part"), b"partial").unwrap();
+        let failed = staging::create_staging_dir(&root, "failed").unwrap();
+        std::fs::write(failed.join("video.mp4"), b"only copy").unwrap();
+        let error = staging::keep_staging_dir(&failed, anyhow::anyhow!("Destination already exists"));
+        assert!(error.to_string().contains(&failed.display().to_string()));
+
+        staging::remove_staging_dir(&failed);
+        assert!(failed.join("video.mp4").exists());
+
+        assert_eq!(staging::cleanup_staging_root(&root), 1);
+        assert!(!leftover.exists());
+        assert!(failed.join("video.mp4").exists());
+        assert!(base.join("notes.txt").exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_metadata_cache() {
+        use crate::metadata_cache::{self, DEFAULT_METADATA_TTL};
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("snapper-metadata-{}", uuid::Uuid::new_v4()));
+        let url = "https://example.com/metadata-cache-test";
+        let data = serde_json::json!({ "id": "abc", "title": "Cached" });
+
+        let stored = metadata_cache::put_in(&dir, url, data.clone()).unwrap();
+        assert!(stored.info_path.starts_with(&dir) && stored.info_path.exists());
+        assert_eq!(*metadata_cache::get_in(&dir, url, DEFAULT_METADATA_TTL).unwrap().data, data);
+
+        // A fresh process only has the copy on disk.
+        metadata_cache::forget(&stored.info_path);
+        let loaded = metadata_cache::get_in(&dir, url, DEFAULT_METADATA_TTL).unwrap();
+        assert_eq!(*loaded.data, data);
+        assert_eq!(loaded.info_path, stored.info_path);
+
+        assert!(metadata_cache::get_in(&dir, url, Duration::ZERO).is_none());
+        assert!(!stored.info_path.exists());
+        assert!(metadata_cache::get_in(&dir, url, DEFAULT_METADATA_TTL).is_none());
+
+        metadata_cache::put_in(&dir, url, data).unwrap();
+        metadata_cache::invalidate_in(&dir, url);
+        assert!(!stored.info_path.exists());
+        assert!(metadata_cache::get_in(&dir, url, DEFAULT_METADATA_TTL).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_collision_policies() {
+        let dir = std::env::temp_dir().join(format!("snapper-collision-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("clip.mp4"), b"existing").unwrap();
+        let resolve = |filename: &str, policy| {
+            match YouTubeDownloader::resolve_collision_in(&dir, filename, "mp4", policy).unwrap() {
+                CollisionResolution::Proceed(name) => name,
+                CollisionResolution::Existing(path) => format!("existing:{}", path.display()),
+            }
+        };
+
+        assert_eq!(resolve("fresh.mp4", CollisionPolicy::Skip), "fresh.mp4");
+        assert_eq!(
+            resolve("clip.mp4", CollisionPolicy::Skip),
+            format!("existing:{}", dir.join("clip.mp4").display())
+        );
+        assert_eq!(resolve("clip.mp4", CollisionPolicy::Overwrite), "clip.mp4");
+        assert_eq!(resolve("clip.mp4", CollisionPolicy::Rename), "clip (1).mp4");
+
+        // Leftover merge parts and sidecar files also claim a name.
+        std::fs::write(dir.join("clip (1).f137.mp4"), b"part").unwrap();
+        std::fs::write(dir.join("talk.en.vtt"), b"subs").unwrap();
+        assert_eq!(resolve("clip.mp4", CollisionPolicy::Rename), "clip (2).mp4");
+        assert_eq!(resolve("talk.mp4", CollisionPolicy::Skip), "talk (1).mp4");
+
+        let long_stem = "x".repeat(223 - ".mp4".len());
+        std::fs::write(dir.join(format!("{}.mp4", long_stem)), b"existing").unwrap();
+        let renamed = resolve(&format!("{}.mp4", long_stem), CollisionPolicy::Rename);
+        assert!(renamed.len() <= 223);
+        assert!(renamed.ends_with(" (1).mp4"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_info_and_clear() {
+        use crate::cache::{cache_info, clear_cache, CacheDirs, JobCache};
+        use crate::staging;
+
+        let root = std::env::temp_dir().join(format!("snapper-cache-{}", uuid::Uuid::new_v4()));
+        let user_dir = root.join("user-folder");
+        let dirs = CacheDirs {
+            root: root.join("cache"),
+            staging: staging::staging_root_in(&user_dir),
+            legacy: root.join("legacy"),
+        };
+        let write = |path: std::path::PathBuf, len: usize| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0u8; len]).unwrap();
+        };
+        write(dirs.root.join("yt-dlp").join("player.json"), 10);
+        write(dirs.root.join("metadata").join("video.info.json"), 20);
+        write(dirs.legacy.join("old.part"), 5);
+        write(user_dir.join("notes.txt"), 1);
+        let leftover = staging::create_staging_dir(&dirs.staging, "leftover").unwrap();
+        write(leftover.join("video.part"), 7);
+        let kept = staging::create_staging_dir(&dirs.staging, "kept").unwrap();
+        write(kept.join("video.mp4"), 3);
+        staging::keep_staging_dir(&kept, anyhow::anyhow!("move failed"));
+
+        // Each download gets its own job folder, removed when the job ends.
+        let jobs = dirs.root.join("jobs");
+        let first = JobCache::create_in(&jobs, &uuid::Uuid::new_v4().to_string()).unwrap();
+        let second = JobCache::create_in(&jobs, &uuid::Uuid::new_v4().to_string()).unwrap();
+        assert_ne!(first.dir(), second.dir());
+        write(first.dir().join("report.txt"), 4);
+        write(second.dir().join("report.txt"), 6);
+
+        let info = cache_info(&dirs);
+        assert_eq!(info.yt_dlp_cache_bytes, 10);
+        assert_eq!(info.metadata_cache_bytes, 20);
+        assert_eq!(info.legacy_cache_bytes, 5);
+        assert_eq!(info.job_cache_bytes, 10);
+        assert_eq!(info.staging_bytes, 10);
+        assert_eq!(info.total_bytes, 55);
+
+        // Running jobs and the shared caches they use are left alone.
+        let result = clear_cache(&dirs);
+        assert_eq!((result.skipped_active, result.skipped_kept), (2, 1));
+        assert_eq!((result.removed_entries, result.reclaimed_bytes), (1, 7));
+        assert!(first.dir().exists() && second.dir().exists());
+        assert!(dirs.root.join("yt-dlp").exists());
+
+        let second_dir = second.dir().to_path_buf();
+        drop(second);
+        assert!(!second_dir.exists());
+        assert!(first.dir().join("report.txt").exists());
+        drop(first);
+
+        let result = clear_cache(&dirs);
+        assert_eq!(result.skipped_kept, 1);
+        assert_eq!(result.reclaimed_bytes, 35);
+        assert!(!dirs.root.join("yt-dlp").exists() && !dirs.legacy.exists());
+        assert!(kept.join("video.mp4").exists());
+        assert!(user_dir.join("notes.txt").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_filename_template_rendering() {
+        use crate::filename::{
+            render_filename_for, strip_media_extension, FilenameContext, FilenameOptions, TargetOs,
+        };
+        use std::path::Path;
+
+        let context = FilenameContext {
+            title: "AC/DC: Back in Black?  (Live) — Żółć".to_string(),
+            uploader: Some("Uploader".to_string()),
+            upload_date: Some("20240101".to_string()),
+            id: "abc123".to_string(),
+            quality: "high".to_string(),
+            ext: "mp4".to_string(),
+            ..Default::default()
+        };
+
+        let options = FilenameOptions::default();
+        assert_eq!(
+            render_filename_for(&options, &context, TargetOs::Unix),
+            PathBuf::from("AC_DC: Back in Black? (Live) — Żółć.mp4")
+        );
+        assert_eq!(
+            render_filename_for(&options, &context, TargetOs::Windows),
+            PathBuf::from("AC_DC_ Back in Black_ (Live) — Żółć.mp4")
+        );
+
+        let options = FilenameOptions {
+            template: "../{uploader}/{upload_date} - {title} [{id}].{ext}".to_string(),
+            ascii_only: true,
+        };
+        assert_eq!(
+            render_filename_for(&options, &context, TargetOs::Unix),
+            PathBuf::from("Uploader/20240101 - AC_DC: Back in Black? (Live) -- Zolc [abc123].mp4")
+        );
+
+        let options = FilenameOptions {
+            template: "CON".to_string(),
+            ascii_only: false,
+        };
+        assert_eq!(
+            render_filename_for(&options, &context, TargetOs::Windows),
+            PathBuf::from("_CON.mp4")
+        );
+
+        let long_context = FilenameContext {
+            title: "ą".repeat(300),
+            ext: "mp3".to_string(),
+            ..Default::default()
+        };
+        let long_name = render_filename_for(&FilenameOptions::default(), &long_context, TargetOs::Unix);
+        let long_name = long_name.to_string_lossy();
+        assert!(long_name.len() <= 255);
+        assert!(long_name.ends_with("ą.mp3"));
+
+        assert_eq!(strip_media_extension(Path::new("v1.2 final")), PathBuf::from("v1.2 final"));
+        assert_eq!(strip_media_extension(Path::new("dir/clip.MP4")), PathBuf::from("dir/clip"));
+        assert_eq!(strip_media_extension(Path::new("notes.txt")), PathBuf::from("notes.txt"));
+    }
+
+    #[test]
+    fn test_parse_output_report() {
+        let report = concat!(
+            "SNAPPER_MERGED [\"/out/Song.f137.mp4\", \"/out/Song.f140.m4a\"]\n",
+            "SNAPPER_FILEPATH /out/Song.mp4\n",
+            "SNAPPER_MOVED {\"/tmp/Song.mp4\": \"/out/Song.mp4\", \"/tmp/Song.en.vtt\": \"/out/Song.en.vtt\"}\n",
+            "SNAPPER_MEDIA {\"resolution\": \"1920x1080\", \"vcodec\": \"avc1.640028\", \"acodec\": \"none\"}\n",
+        );
+
+        let output = parse_output_report(report).expect("Failed to parse report");
+        assert_eq!(output.file_path, PathBuf::from("/out/Song.mp4"));
+        assert_eq!(
+            output.files,
+            vec![PathBuf::from("/out/Song.mp4"), PathBuf::from("/out/Song.en.vtt")]
+        );
+        assert_eq!(output.intermediates.len(), 2);
+        assert_eq!(output.media.resolution.as_deref(), Some("1920x1080"));
+        assert_eq!(output.media.video_codec.as_deref(), Some("avc1.640028"));
+        assert_eq!(output.media.audio_codec, None);
+
+        assert!(parse_output_report("SNAPPER_FILEPATH NA\n").is_err());
+    }
+
+    #[test]
+    fn test_download_history_details() {
+        // Entries stored before the extra fields existed still load.
+        let legacy: DownloadHistory = serde_json::from_str(
+            r#"{"id": "1", "title": "Old", "url": "https://youtu.be/dQw4w9WgXcQ", "status": "Completed",
+                "downloaded_at": "2024-01-01T00:00:00Z", "file_path": null, "format": "mp4", "quality": "high"}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.attempts, 0);
+        assert!(legacy.uploader.is_none() && legacy.error.is_none() && legacy.started_at.is_none());
+
+        let failure = DownloadFailure::from_message(concat!(
+            "WARNING: [youtube] Falling back to generic n function search\n",
+            "ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video\n",
+        ));
+        assert_eq!(failure.category, ErrorCategory::Restricted);
+        assert!(failure.message.starts_with("ERROR: [youtube]"));
+        assert_eq!(
+            ErrorCategory::classify("ERROR: Requested format is not available"),
+            ErrorCategory::FormatUnavailable
+        );
+        assert_eq!(
+            ErrorCategory::classify("Download failed: Connection reset by peer"),
+            ErrorCategory::Network
+        );
+        assert_eq!(ErrorCategory::classify("something odd"), ErrorCategory::Other);
+
+        let path = std::env::temp_dir().join(format!("snapper-details-{}.mp4", uuid::Uuid::new_v4()));
+        std::fs::write(&path, vec![0u8; 4096]).unwrap();
+        let mut entry = DownloadHistory {
+            status: DownloadStatus::Completed,
+            file_path: Some(path.to_string_lossy().to_string()),
+            started_at: Some(chrono::Utc::now() - chrono::Duration::seconds(2)),
+            ..Default::default()
+        };
+        entry.record_finish();
+        assert_eq!(entry.file_size, Some(4096));
+        assert!(entry.finished_at.is_some());
+        let speed = entry.average_speed.unwrap();
+        assert!(speed > 1000.0 && speed <= 2048.0, "speed {}", speed);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_video_info_details() {
+        use crate::youtube::{LiveStatus, YouTubeDownloader};
+
+        let video_data = serde_json::json!({
+            "id": "abc123",
+            "title": "Premiere",
+            "description": "Line one\nLine two",
+            "channel_id": "UC123",
+            "channel_url": "https://www.youtube.com/channel/UC123",
+            "tags": ["music", "live"],
+            "categories": ["Music"],
+            "like_count": 42,
+            "age_limit": 18,
+            "availability": "needs_auth",
+            "live_status": "is_upcoming",
+            "release_timestamp": 1_700_000_000,
+            "width": 1920,
+            "height": 1080,
+            "chapters": [
+                { "title": "Intro", "start_time": 0.0, "end_time": 30.0 },
+                { "title": "Main", "start_time": 30.0, "end_time": 120.0 }
+            ],
+            "subtitles": { "pl": [], "en": [] },
+            "automatic_captions": { "de": [] },
+            "formats": []
+        });
+
+        let info = YouTubeDownloader::parse_video_info("https://youtu.be/abc123", &video_data);
+        assert_eq!(info.channel_id.as_deref(), Some("UC123"));
+        assert_eq!(info.tags, ["music", "live"]);
+        assert_eq!(info.age_limit, Some(18));
+        assert_eq!(info.live_status, LiveStatus::IsUpcoming);
+        assert_eq!(info.scheduled_start.map(|t| t.timestamp()), Some(1_700_000_000));
+        assert_eq!(info.aspect_ratio, Some(1.78));
+        assert_eq!(info.chapters.len(), 2);
+        assert_eq!(info.subtitle_languages, ["en", "pl"]);
+        assert_eq!(info.automatic_caption_languages, ["de"]);
+
+        let finished = serde_json::json!({
+            "id": "abc123",
+            "live_status": "was_live",
+            "release_timestamp": 1_700_000_000
+        });
+        let info = YouTubeDownloader::parse_video_info("https://youtu.be/abc123", &finished);
+        assert_eq!(info.live_status, LiveStatus::WasLive);
+        assert_eq!(info.scheduled_start, None);
+
+        let legacy = serde_json::json!({ "id": "x", "title": "Stream", "is_live": true });
+        let info = YouTubeDownloader::parse_video_info("https://youtu.be/x", &legacy);
+        assert_eq!(info.live_status, LiveStatus::IsLive);
+        assert!(info.tags.is_empty());
+    }
+
+    #[test]
+    fn test_estimate_download_size() {
+        use crate::diskspace::estimate_download_size;
+
+        let video_data = serde_json::json!({
+            "duration": 100,
+            "formats": [
+                { "format_id": "140", "vcodec": "none", "acodec": "mp4a", "abr": 128.0, "filesize": 5_000_000 },
+                { "format_id": "137", "vcodec": "avc1", "acodec": "none", "height": 1080, "filesize": 100_000_000 },
+                { "format_id": "313", "vcodec": "vp9", "acodec": "none", "height": 2160, "filesize_approx": 300_000_000 },
+                { "format_id": "18", "vcodec": "avc1", "acodec": "mp4a", "height": 360, "tbr": 800.0 }
+            ]
+        });
+
+        let high = estimate_download_size(&video_data, "mp4", "high", 1080).unwrap();
+        assert_eq!(high.download_bytes, 105_000_000);
+        assert!(high.final_bytes > high.download_bytes);
+
+        let best = estimate_download_size(&video_data, "mp4", "best", 2160).unwrap();
+        assert_eq!(best.download_bytes, 305_000_000);
+
+        let wav = estimate_download_size(&video_data, "wav", "best", 2160).unwrap();
+        assert_eq!(wav.download_bytes, 5_000_000);
+        assert_eq!(wav.final_bytes, 17_640_000);
+
+        assert!(estimate_download_size(&serde_json::json!({}), "mp4", "high", 1080).is_none());
+    }
+
     #[tokio::test]
     async fn test_api_supported_formats() {
         let result = crate::commands::get_supported_formats().await;
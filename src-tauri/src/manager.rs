@@ -0,0 +1,195 @@
+use crate::backend::{Binaries, CancellationToken};
+use crate::commands::AppSettings;
+use crate::youtube::DownloadRequest;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedDownload {
+    pub id: String,
+    pub request: DownloadRequest,
+    pub queued_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct ActiveJob {
+    request: DownloadRequest,
+    cancel: CancellationToken,
+    started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveDownload {
+    pub id: String,
+    pub url: String,
+    pub format: String,
+    pub started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSnapshot {
+    pub active: Vec<ActiveDownload>,
+    pub queued: Vec<QueuedDownload>,
+    pub max_concurrent: usize,
+}
+
+/// What `cancel` found for a download id.
+#[derive(Debug)]
+pub enum Cancellation {
+    /// The job was running; its token has been cancelled.
+    Running,
+    /// The job was still waiting and has been taken off the queue.
+    Dequeued(QueuedDownload),
+    NotFound,
+}
+
+/// App-wide download state kept in Tauri managed state: the current settings,
+/// the binaries resolved for them, running jobs and the queue of downloads
+/// waiting for a free slot.
+#[derive(Default)]
+pub struct DownloadManager {
+    settings: Mutex<Option<AppSettings>>,
+    binaries: Mutex<Option<Binaries>>,
+    active: Mutex<HashMap<String, ActiveJob>>,
+    queue: Mutex<VecDeque<QueuedDownload>>,
+}
+
+impl DownloadManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn settings(&self) -> Option<AppSettings> {
+        self.settings.lock().ok()?.clone()
+    }
+
+    /// Replaces the cached settings and resolves the binaries again, since the
+    /// backend or search paths they depend on may have changed.
+    pub fn update_settings(&self, settings: AppSettings) {
+        if let Ok(mut current) = self.settings.lock() {
+            *current = Some(settings);
+        }
+        let resolved = Self::resolve_binaries();
+        if let Ok(mut binaries) = self.binaries.lock() {
+            *binaries = resolved;
+        }
+    }
+
+    /// Binaries resolved for the current settings, looked up on first use.
+    /// `None` leaves backends to report the missing dependency themselves.
+    pub fn binaries(&self) -> Option<Binaries> {
+        let mut binaries = self.binaries.lock().ok()?;
+        if binaries.is_none() {
+            *binaries = Self::resolve_binaries();
+        }
+        binaries.clone()
+    }
+
+    fn resolve_binaries() -> Option<Binaries> {
+        match Binaries::resolve() {
+            Ok(binaries) => Some(binaries),
+            Err(e) => {
+                eprintln!("Failed to resolve downloader binaries: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn enqueue(&self, id: &str, request: DownloadRequest) {
+        if let Ok(mut queue) = self.queue.lock() {
+            queue.push_back(QueuedDownload {
+                id: id.to_string(),
+                request,
+                queued_at: Utc::now(),
+            });
+        }
+    }
+
+    /// Moves the oldest queued download into the active set if fewer than
+    /// `max_concurrent` are running, returning it with its cancellation token.
+    pub fn start_next(&self, max_concurrent: usize) -> Option<(QueuedDownload, CancellationToken)> {
+        let mut active = self.active.lock().ok()?;
+        if active.len() >= max_concurrent.max(1) {
+            return None;
+        }
+        let next = self.queue.lock().ok()?.pop_front()?;
+        let cancel = CancellationToken::new();
+        active.insert(
+            next.id.clone(),
+            ActiveJob {
+                request: next.request.clone(),
+                cancel: cancel.clone(),
+                started_at: Utc::now(),
+            },
+        );
+        Some((next, cancel))
+    }
+
+    pub fn finish(&self, id: &str) {
+        if let Ok(mut active) = self.active.lock() {
+            active.remove(id);
+        }
+    }
+
+    pub fn cancel(&self, id: &str) -> Cancellation {
+        if let Some(job) = self.active.lock().ok().and_then(|active| active.get(id).cloned()) {
+            job.cancel.cancel();
+            return Cancellation::Running;
+        }
+
+        let Ok(mut queue) = self.queue.lock() else {
+            return Cancellation::NotFound;
+        };
+        match queue.iter().position(|queued| queued.id == id) {
+            Some(index) => queue
+                .remove(index)
+                .map(Cancellation::Dequeued)
+                .unwrap_or(Cancellation::NotFound),
+            None => Cancellation::NotFound,
+        }
+    }
+
+    /// Whether `id` is running or waiting in the queue.
+    pub fn has_job(&self, id: &str) -> bool {
+        let running = self.active.lock().map(|active| active.contains_key(id)).unwrap_or(false);
+        running || self.queue.lock().map(|queue| queue.iter().any(|queued| queued.id == id)).unwrap_or(false)
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.lock().map(|active| active.len()).unwrap_or(0)
+    }
+
+    pub fn queued(&self) -> Vec<QueuedDownload> {
+        self.queue
+            .lock()
+            .map(|queue| queue.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn snapshot(&self, max_concurrent: usize) -> QueueSnapshot {
+        let mut active: Vec<ActiveDownload> = self
+            .active
+            .lock()
+            .map(|active| {
+                active
+                    .iter()
+                    .map(|(id, job)| ActiveDownload {
+                        id: id.clone(),
+                        url: job.request.url.clone(),
+                        format: job.request.format.clone(),
+                        started_at: job.started_at,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        active.sort_by_key(|job| job.started_at);
+
+        QueueSnapshot {
+            active,
+            queued: self.queued(),
+            max_concurrent,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::cache;
+use crate::urls::{self, UrlAnalysis};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+pub const DEFAULT_METADATA_TTL: Duration = Duration::from_secs(60 * 60);
+pub const METADATA_DIR_NAME: &str = "metadata";
+
+#[derive(Clone)]
+pub struct CachedMetadata {
+    pub data: Arc<Value>,
+    pub info_path: PathBuf,
+    fetched_at: SystemTime,
+}
+
+/// Entries already read, keyed by their info JSON path.
+fn memory_cache() -> &'static Mutex<HashMap<PathBuf, CachedMetadata>> {
+    static MEMORY_CACHE: OnceLock<Mutex<HashMap<PathBuf, CachedMetadata>>> = OnceLock::new();
+    MEMORY_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn metadata_dir() -> PathBuf {
+    cache::cache_root().join(METADATA_DIR_NAME)
+}
+
+pub fn cache_key(url: &str) -> String {
+    match urls::analyze_url(url) {
+        Ok(UrlAnalysis {
+            video_id: Some(id), ..
+        }) => format!("youtube:{}", id),
+        Ok(analysis) => analysis.canonical_url,
+        Err(_) => url.trim().trim_end_matches('/').to_string(),
+    }
+}
+
+fn info_path(dir: &Path, url: &str) -> PathBuf {
+    let digest = Sha256::digest(cache_key(url).as_bytes());
+    dir.join(format!("{}.info.json", hex::encode(digest)))
+}
+
+fn is_fresh(fetched_at: SystemTime, ttl: Duration) -> bool {
+    SystemTime::now()
+        .duration_since(fetched_at)
+        .map(|age| age < ttl)
+        .unwrap_or(false)
+}
+
+pub fn get(url: &str, ttl: Duration) -> Option<CachedMetadata> {
+    get_in(&metadata_dir(), url, ttl)
+}
+
+pub fn get_in(dir: &Path, url: &str, ttl: Duration) -> Option<CachedMetadata> {
+    let path = info_path(dir, url);
+
+    if let Ok(mut memory) = memory_cache().lock() {
+        match memory.get(&path) {
+            Some(entry) if is_fresh(entry.fetched_at, ttl) && entry.info_path.exists() => {
+                return Some(entry.clone());
+            }
+            Some(_) => {
+                memory.remove(&path);
+            }
+            None => {}
+        }
+    }
+
+    let fetched_at = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    if !is_fresh(fetched_at, ttl) {
+        let _ = std::fs::remove_file(&path);
+        return None;
+    }
+
+    let contents = std::fs::read_to_string(&path).ok()?;
+    let data: Value = serde_json::from_str(&contents).ok()?;
+    let entry = CachedMetadata {
+        data: Arc::new(data),
+        info_path: path.clone(),
+        fetched_at,
+    };
+
+    if let Ok(mut memory) = memory_cache().lock() {
+        memory.insert(path, entry.clone());
+    }
+    Some(entry)
+}
+
+pub fn put(url: &str, data: Value) -> Result<CachedMetadata> {
+    put_in(&metadata_dir(), url, data)
+}
+
+pub fn put_in(dir: &Path, url: &str, data: Value) -> Result<CachedMetadata> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow!("Failed to create metadata cache directory: {}", e))?;
+
+    let path = info_path(dir, url);
+    let partial = path.with_extension(format!("{}.partial", uuid::Uuid::new_v4()));
+    std::fs::write(&partial, serde_json::to_vec(&data)?)?;
+    std::fs::rename(&partial, &path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&partial);
+    })?;
+
+    let entry = CachedMetadata {
+        data: Arc::new(data),
+        info_path: path.clone(),
+        fetched_at: SystemTime::now(),
+    };
+
+    if let Ok(mut memory) = memory_cache().lock() {
+        memory.insert(path, entry.clone());
+    }
+    Ok(entry)
+}
+
+pub fn invalidate(url: &str) {
+    invalidate_in(&metadata_dir(), url)
+}
+
+pub fn invalidate_in(dir: &Path, url: &str) {
+    let path = info_path(dir, url);
+    forget(&path);
+    let _ = std::fs::remove_file(path);
+}
+
+/// Drops the in-memory copy only, leaving the info JSON on disk.
+pub fn forget(info_path: &Path) {
+    if let Ok(mut memory) = memory_cache().lock() {
+        memory.remove(info_path);
+    }
+}
+
+pub fn clear_memory() {
+    if let Ok(mut memory) = memory_cache().lock() {
+        memory.clear();
+    }
+}
@@ -0,0 +1,376 @@
+use crate::filename;
+use crate::security;
+use crate::youtube::{CollisionPolicy, MAX_RENAME_ATTEMPTS};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PostProcessStep {
+    /// Trims leading and trailing audio quieter than `threshold_db`. Trimming
+    /// stops at the first stretch of sound lasting `min_duration` seconds, so
+    /// brief clicks inside the silence are trimmed with it.
+    TrimSilence {
+        #[serde(default = "default_silence_threshold_db")]
+        threshold_db: f64,
+        #[serde(default = "default_silence_min_duration")]
+        min_duration: f64,
+    },
+    Crop {
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        x: u32,
+        #[serde(default)]
+        y: u32,
+    },
+    Scale {
+        width: i32,
+        height: i32,
+    },
+    ChangeFps {
+        fps: f64,
+    },
+    StripMetadata,
+    Convert {
+        format: String,
+    },
+}
+
+fn default_silence_threshold_db() -> f64 {
+    -50.0
+}
+
+fn default_silence_min_duration() -> f64 {
+    0.5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostProcessPipeline {
+    pub name: String,
+    pub steps: Vec<PostProcessStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PostProcessStepStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostProcessProgress {
+    pub download_id: String,
+    pub pipeline: String,
+    pub step_index: usize,
+    pub step_count: usize,
+    pub step: String,
+    pub status: PostProcessStepStatus,
+    pub progress: f64,
+    pub error: Option<String>,
+}
+
+impl PostProcessStep {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostProcessStep::TrimSilence { .. } => "trim_silence",
+            PostProcessStep::Crop { .. } => "crop",
+            PostProcessStep::Scale { .. } => "scale",
+            PostProcessStep::ChangeFps { .. } => "change_fps",
+            PostProcessStep::StripMetadata => "strip_metadata",
+            PostProcessStep::Convert { .. } => "convert",
+        }
+    }
+
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        match self {
+            PostProcessStep::TrimSilence {
+                threshold_db,
+                min_duration,
+            } => {
+                let remove_leading = format!(
+                    "silenceremove=start_periods=1:start_threshold={}dB:start_duration={}",
+                    threshold_db, min_duration
+                );
+                vec![
+                    "-af".to_string(),
+                    format!(
+                        "{},areverse,{},areverse",
+                        remove_leading, remove_leading
+                    ),
+                    "-c:v".to_string(),
+                    "copy".to_string(),
+                ]
+            }
+            PostProcessStep::Crop {
+                width,
+                height,
+                x,
+                y,
+            } => vec![
+                "-vf".to_string(),
+                format!("crop={}:{}:{}:{}", width, height, x, y),
+                "-c:a".to_string(),
+                "copy".to_string(),
+            ],
+            PostProcessStep::Scale { width, height } => vec![
+                "-vf".to_string(),
+                format!("scale={}:{}", width, height),
+                "-c:a".to_string(),
+                "copy".to_string(),
+            ],
+            PostProcessStep::ChangeFps { fps } => vec![
+                "-vf".to_string(),
+                format!("fps={}", fps),
+                "-c:a".to_string(),
+                "copy".to_string(),
+            ],
+            PostProcessStep::StripMetadata => vec![
+                "-map".to_string(),
+                "0".to_string(),
+                "-map_metadata".to_string(),
+                "-1".to_string(),
+                "-map_chapters".to_string(),
+                "-1".to_string(),
+                "-c".to_string(),
+                "copy".to_string(),
+            ],
+            PostProcessStep::Convert { .. } => Vec::new(),
+        }
+    }
+
+    fn output_extension<'a>(&'a self, current: &'a str) -> &'a str {
+        match self {
+            PostProcessStep::Convert { format } => format.as_str(),
+            _ => current,
+        }
+    }
+}
+
+impl PostProcessProgress {
+    /// Reports that `pipeline` stopped at `step_index` with `error`.
+    pub fn failed(
+        pipeline: &PostProcessPipeline,
+        download_id: &str,
+        step_index: usize,
+        error: String,
+    ) -> Self {
+        Self {
+            download_id: download_id.to_string(),
+            pipeline: pipeline.name.clone(),
+            step_index,
+            step_count: pipeline.steps.len(),
+            step: pipeline.steps.get(step_index).map(PostProcessStep::name).unwrap_or_default().to_string(),
+            status: PostProcessStepStatus::Failed,
+            progress: 0.0,
+            error: Some(error),
+        }
+    }
+}
+
+pub struct PostProcessor {
+    ffmpeg_path: PathBuf,
+}
+
+impl PostProcessor {
+    pub fn new(ffmpeg_path: PathBuf) -> Self {
+        Self { ffmpeg_path }
+    }
+
+    pub async fn run<F>(
+        &self,
+        pipeline: &PostProcessPipeline,
+        input: &Path,
+        download_id: &str,
+        duration: Option<u64>,
+        policy: CollisionPolicy,
+        on_progress: F,
+    ) -> Result<PathBuf>
+    where
+        F: Fn(PostProcessProgress),
+    {
+        if pipeline.steps.is_empty() {
+            return Ok(input.to_path_buf());
+        }
+
+        let parent = input
+            .parent()
+            .ok_or_else(|| anyhow!("Cannot get parent directory of {}", input.display()))?;
+        let stem = input
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("Invalid file name: {}", input.display()))?;
+        let original_ext = input
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let step_count = pipeline.steps.len();
+        let mut intermediates: Vec<PathBuf> = Vec::new();
+        let mut current = input.to_path_buf();
+        let mut current_ext = original_ext.clone();
+
+        for (index, step) in pipeline.steps.iter().enumerate() {
+            let report = |status: PostProcessStepStatus, progress: f64, error: Option<String>| {
+                on_progress(PostProcessProgress {
+                    download_id: download_id.to_string(),
+                    pipeline: pipeline.name.clone(),
+                    step_index: index,
+                    step_count,
+                    step: step.name().to_string(),
+                    status,
+                    progress,
+                    error,
+                })
+            };
+
+            let next_ext = step.output_extension(&current_ext).to_string();
+            let next = parent.join(format!("{}.snapper-pp{}.{}", stem, index, next_ext));
+
+            report(PostProcessStepStatus::Running, 0.0, None);
+
+            match self
+                .run_step(step, &current, &next, duration, |p| {
+                    report(PostProcessStepStatus::Running, p, None)
+                })
+                .await
+            {
+                Ok(()) => {
+                    report(PostProcessStepStatus::Completed, 100.0, None);
+                    intermediates.push(next.clone());
+                    current = next;
+                    current_ext = next_ext;
+                }
+                Err(e) => {
+                    report(PostProcessStepStatus::Failed, 0.0, Some(e.to_string()));
+                    let _ = std::fs::remove_file(&next);
+                    Self::remove_intermediates(&intermediates);
+                    return Err(anyhow!("Post-processing step '{}' failed: {}", step.name(), e));
+                }
+            }
+        }
+
+        let final_path = Self::final_path(parent, &stem, &current_ext, input, policy)
+            .and_then(|final_path| {
+                std::fs::rename(&current, &final_path)
+                    .map_err(|e| anyhow!("Failed to move post-processed file into place: {}", e))?;
+                Ok(final_path)
+            })
+            .inspect_err(|e| {
+                on_progress(PostProcessProgress::failed(pipeline, download_id, step_count - 1, e.to_string()));
+                Self::remove_intermediates(&intermediates);
+            })?;
+
+        if final_path != input {
+            if let Err(e) = std::fs::remove_file(input) {
+                eprintln!("Warning: Failed to remove original file {:?}: {}", input, e);
+            }
+        }
+
+        intermediates.retain(|p| *p != current);
+        Self::remove_intermediates(&intermediates);
+
+        Ok(final_path)
+    }
+
+    /// Where the result of a pipeline goes. A different file already holding
+    /// that name is handled by `policy`, as for the download itself.
+    fn final_path(
+        parent: &Path,
+        stem: &str,
+        ext: &str,
+        input: &Path,
+        policy: CollisionPolicy,
+    ) -> Result<PathBuf> {
+        let path = parent.join(format!("{}.{}", stem, ext));
+        if path == input || !path.exists() {
+            return Ok(path);
+        }
+        match policy {
+            CollisionPolicy::Overwrite => Ok(path),
+            CollisionPolicy::Skip => Err(anyhow!("{} already exists", path.display())),
+            CollisionPolicy::Rename => (1..=MAX_RENAME_ATTEMPTS)
+                .map(|index| parent.join(format!("{}.{}", filename::numbered_stem(stem, index, ext), ext)))
+                .find(|candidate| !candidate.exists())
+                .ok_or_else(|| anyhow!("No free file name for {}", path.display())),
+        }
+    }
+
+    async fn run_step<F>(
+        &self,
+        step: &PostProcessStep,
+        input: &Path,
+        output: &Path,
+        duration: Option<u64>,
+        on_progress: F,
+    ) -> Result<()>
+    where
+        F: Fn(f64),
+    {
+        let mut cmd = security::clean_async_command(&self.ffmpeg_path);
+        cmd.args(["-hide_banner", "-nostats", "-y", "-progress", "pipe:1", "-i"])
+            .arg(input)
+            .args(step.ffmpeg_args())
+            .arg(output)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        println!("Running post-processing step {}: {:?}", step.name(), cmd);
+        let mut child = cmd.spawn()?;
+
+        let stderr = child.stderr.take();
+        let stderr_task = tokio::spawn(async move {
+            let mut collected = String::new();
+            if let Some(stderr) = stderr {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    collected.push_str(&line);
+                    collected.push('\n');
+                }
+            }
+            collected
+        });
+
+        if let Some(stdout) = child.stdout.take() {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Some(line) = lines.next_line().await? {
+                if let (Some(value), Some(total)) = (line.strip_prefix("out_time_us="), duration) {
+                    if let Ok(micros) = value.trim().parse::<u64>() {
+                        if total > 0 {
+                            let percent = (micros as f64 / 1_000_000.0) / total as f64 * 100.0;
+                            on_progress(percent.min(100.0));
+                        }
+                    }
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        let stderr = stderr_task.await.unwrap_or_default();
+
+        if !status.success() {
+            let tail: Vec<&str> = stderr.lines().rev().take(5).collect();
+            let tail: Vec<&str> = tail.into_iter().rev().collect();
+            return Err(anyhow!("ffmpeg exited with {}: {}", status, tail.join("\n")));
+        }
+
+        if !output.exists() {
+            return Err(anyhow!("ffmpeg did not produce {}", output.display()));
+        }
+
+        Ok(())
+    }
+
+    fn remove_intermediates(paths: &[PathBuf]) {
+        for path in paths {
+            if path.exists() {
+                if let Err(e) = std::fs::remove_file(path) {
+                    eprintln!("Warning: Failed to remove intermediate file {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+}
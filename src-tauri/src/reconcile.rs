@@ -0,0 +1,241 @@
+use crate::youtube::{DownloadHistory, DownloadStatus};
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const FINGERPRINT_CHUNK: u64 = 64 * 1024;
+const MAX_SCAN_DEPTH: usize = 6;
+const MAX_SCANNED_FILES: usize = 200_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub present: usize,
+    pub missing: usize,
+    pub relocated: usize,
+}
+
+/// Cheap content fingerprint: the size plus hashes of the first and last
+/// 64 KiB, enough to tell a moved download from another file of equal size.
+pub fn fingerprint(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+
+    let mut buffer = vec![0u8; FINGERPRINT_CHUNK as usize];
+    let read = file.read(&mut buffer)?;
+    hasher.update(&buffer[..read]);
+    if size > FINGERPRINT_CHUNK {
+        file.seek(SeekFrom::Start(size.saturating_sub(FINGERPRINT_CHUNK).max(FINGERPRINT_CHUNK)))?;
+        let read = file.read(&mut buffer)?;
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Files below the download roots, indexed by name and by size.
+#[derive(Default)]
+pub struct FileIndex {
+    by_name: HashMap<OsString, Vec<PathBuf>>,
+    by_size: HashMap<u64, Vec<PathBuf>>,
+}
+
+impl FileIndex {
+    pub fn build(roots: &[PathBuf]) -> Self {
+        let mut index = Self::default();
+        let mut scanned = 0;
+        for root in roots {
+            index.scan(root, 0, &mut scanned);
+        }
+        index
+    }
+
+    fn scan(&mut self, dir: &Path, depth: usize, scanned: &mut usize) {
+        if depth > MAX_SCAN_DEPTH || *scanned >= MAX_SCANNED_FILES {
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                self.scan(&path, depth + 1, scanned);
+            } else if file_type.is_file() {
+                *scanned += 1;
+                if *scanned > MAX_SCANNED_FILES {
+                    return;
+                }
+                let paths = self.by_name.entry(name).or_default();
+                // Roots may overlap, e.g. ~/Downloads and a folder inside it.
+                if paths.contains(&path) {
+                    continue;
+                }
+                paths.push(path.clone());
+                if let Ok(metadata) = entry.metadata() {
+                    self.by_size.entry(metadata.len()).or_default().push(path);
+                }
+            }
+        }
+    }
+
+    /// Finds where a missing file went: first by file name, then, for renamed
+    /// files, by size. The recorded size must match, and the fingerprint too
+    /// when there is one. Without a recorded size nothing is relocated, and
+    /// only an unambiguous match is returned.
+    pub fn locate(&self, original: &Path, size: Option<u64>, hash: Option<&str>) -> Option<PathBuf> {
+        // A shared name alone says little about whether it is the same file.
+        let size = size?;
+        let matches = |path: &PathBuf| -> bool {
+            if std::fs::metadata(path).map(|m| m.len()).ok() != Some(size) {
+                return false;
+            }
+            match hash {
+                Some(hash) => fingerprint(path).map(|f| f == hash).unwrap_or(false),
+                None => true,
+            }
+        };
+
+        let by_name: Vec<&PathBuf> = original
+            .file_name()
+            .and_then(|name| self.by_name.get(name))
+            .map(|paths| paths.iter().filter(|p| matches(p)).collect())
+            .unwrap_or_default();
+        if let [found] = by_name.as_slice() {
+            return Some((*found).clone());
+        }
+        if !by_name.is_empty() {
+            return None;
+        }
+
+        // Matching on content alone needs the fingerprint as well.
+        hash?;
+        let by_content: Vec<&PathBuf> = self
+            .by_size
+            .get(&size)
+            .map(|paths| paths.iter().filter(|p| matches(p)).collect())
+            .unwrap_or_default();
+        match by_content.as_slice() {
+            [found] => Some((*found).clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reconciled {
+    Present,
+    Relocated(PathBuf),
+    Missing,
+}
+
+/// Entries whose file verification should look at.
+pub fn is_verifiable(entry: &DownloadHistory) -> bool {
+    entry.file_path.is_some()
+        && matches!(
+            entry.status,
+            DownloadStatus::Completed | DownloadStatus::Skipped | DownloadStatus::Missing
+        )
+}
+
+/// Checks one entry against the filesystem and updates it in place; the flag
+/// says whether anything changed.
+pub fn reconcile_entry(
+    entry: &mut DownloadHistory,
+    index: &mut Option<FileIndex>,
+    roots: &[PathBuf],
+) -> (Reconciled, bool) {
+    let Some(path) = entry.file_path.clone().map(PathBuf::from) else {
+        return (Reconciled::Missing, false);
+    };
+
+    if path.is_file() {
+        let changed = record_fingerprint(entry, &path);
+        return (Reconciled::Present, restore_status(entry) || changed);
+    }
+
+    let index = index.get_or_insert_with(|| FileIndex::build(roots));
+    match index.locate(&path, entry.file_size, entry.file_hash.as_deref()) {
+        Some(found) => {
+            let old = path.to_string_lossy().to_string();
+            let new = found.to_string_lossy().to_string();
+            for output in entry.output_files.iter_mut().filter(|p| **p == old) {
+                *output = new.clone();
+            }
+            entry.file_path = Some(new);
+            restore_status(entry);
+            record_fingerprint(entry, &found);
+            (Reconciled::Relocated(found), true)
+        }
+        None => {
+            if matches!(entry.status, DownloadStatus::Missing) {
+                return (Reconciled::Missing, false);
+            }
+            let previous = std::mem::replace(&mut entry.status, DownloadStatus::Missing);
+            entry.status_before_missing = Some(previous);
+            (Reconciled::Missing, true)
+        }
+    }
+}
+
+/// Takes a `Missing` entry back to the status it had before it went missing,
+/// so a skipped download stays skipped. Entries marked missing before that
+/// status was kept fall back to completed.
+fn restore_status(entry: &mut DownloadHistory) -> bool {
+    if !matches!(entry.status, DownloadStatus::Missing) {
+        return false;
+    }
+    entry.status = entry.status_before_missing.take().unwrap_or(DownloadStatus::Completed);
+    true
+}
+
+fn record_fingerprint(entry: &mut DownloadHistory, path: &Path) -> bool {
+    if entry.file_size.is_some() && entry.file_hash.is_some() {
+        return false;
+    }
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    entry.file_size = Some(metadata.len());
+    entry.file_hash = fingerprint(path).ok();
+    true
+}
+
+/// Reconciles every verifiable entry, returning the counts and the entries
+/// that changed.
+pub fn verify_entries(
+    entries: Vec<DownloadHistory>,
+    roots: &[PathBuf],
+) -> (VerifyReport, Vec<DownloadHistory>) {
+    let mut report = VerifyReport::default();
+    let mut changed = Vec::new();
+    let mut index = None;
+
+    for mut entry in entries.into_iter().filter(is_verifiable) {
+        report.checked += 1;
+        let (outcome, modified) = reconcile_entry(&mut entry, &mut index, roots);
+        match outcome {
+            Reconciled::Present => report.present += 1,
+            Reconciled::Relocated(_) => report.relocated += 1,
+            Reconciled::Missing => report.missing += 1,
+        }
+        if modified {
+            changed.push(entry);
+        }
+    }
+
+    (report, changed)
+}
@@ -0,0 +1,188 @@
+use crate::backend::{BackendOptions, CancellationToken, FormatInfo, MediaBackend};
+use crate::filename::{self, FilenameContext};
+use crate::sites::{self, SiteConfig};
+use crate::youtube::{DownloadHistory, DownloadProgress, DownloadRequest, DownloadStatus, VideoInfo};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const WILDCARD_URL: &str = "*";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScriptedOutcome {
+    #[default]
+    Complete,
+    Fail {
+        message: String,
+    },
+}
+
+/// Canned answers for the scripted backend. `videos` is keyed by URL, with
+/// `"*"` matching any URL that has no entry of its own.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackendScript {
+    #[serde(default)]
+    pub videos: HashMap<String, VideoInfo>,
+    #[serde(default)]
+    pub formats: Vec<FormatInfo>,
+    #[serde(default)]
+    pub extractor: Option<String>,
+    #[serde(default)]
+    pub progress: Vec<f64>,
+    #[serde(default)]
+    pub step_delay_ms: u64,
+    #[serde(default)]
+    pub outcome: ScriptedOutcome,
+}
+
+/// Offline stand-in that replays a `BackendScript` instead of touching the
+/// network, so code written against `MediaBackend` can be exercised without
+/// yt-dlp. Only built for tests, as it writes placeholder files.
+pub struct ScriptedBackend {
+    script: BackendScript,
+    options: BackendOptions,
+}
+
+impl ScriptedBackend {
+    pub fn new(script: BackendScript, options: BackendOptions) -> Self {
+        Self { script, options }
+    }
+
+    pub fn from_file(path: &str, options: BackendOptions) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read backend script {}: {}", path, e))?;
+        let script = serde_json::from_str(&contents)
+            .map_err(|e| anyhow!("Invalid backend script {}: {}", path, e))?;
+        Ok(Self::new(script, options))
+    }
+
+    fn video(&self, url: &str) -> Result<VideoInfo> {
+        self.script
+            .videos
+            .get(url)
+            .or_else(|| self.script.videos.get(WILDCARD_URL))
+            .cloned()
+            .ok_or_else(|| anyhow!("No scripted video for {}", url))
+    }
+
+    fn extractor(&self) -> &str {
+        self.script.extractor.as_deref().unwrap_or(sites::YOUTUBE_EXTRACTOR)
+    }
+
+    fn report(&self, progress: DownloadProgress) {
+        if let Some(callback) = &self.options.progress_callback {
+            callback(progress);
+        }
+    }
+}
+
+#[async_trait]
+impl MediaBackend for ScriptedBackend {
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+
+    async fn check_dependencies(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn ffmpeg_path(&self) -> Result<PathBuf> {
+        Err(anyhow!("The scripted backend does not provide ffmpeg"))
+    }
+
+    async fn probe(&self, url: &str, _refresh: bool) -> Result<VideoInfo> {
+        self.video(url)
+    }
+
+    async fn list_formats(&self, url: &str) -> Result<Vec<FormatInfo>> {
+        self.video(url)?;
+        Ok(self.script.formats.clone())
+    }
+
+    async fn identify_site(&self, url: &str, _extract: bool) -> Result<SiteConfig> {
+        self.video(url)?;
+        match sites::find_site(&self.options.sites, self.extractor()) {
+            Some(site) if site.enabled => Ok(site.clone()),
+            _ => Err(anyhow!("Extractor '{}' is not enabled", self.extractor())),
+        }
+    }
+
+    fn cached_video_info(&self, url: &str) -> Option<VideoInfo> {
+        self.video(url).ok()
+    }
+
+    async fn download(
+        &self,
+        request: DownloadRequest,
+        download_id: String,
+        cancel: CancellationToken,
+    ) -> Result<DownloadHistory> {
+        let video = self.video(&request.url)?;
+        let mut history = DownloadHistory {
+            id: download_id.clone(),
+            title: video.title.clone(),
+            url: request.url.clone(),
+            status: DownloadStatus::Downloading,
+            downloaded_at: Utc::now(),
+            file_path: None,
+            format: request.format.clone(),
+            quality: request.quality.clone(),
+            output_files: Vec::new(),
+            hook_results: Vec::new(),
+            extractor: Some(self.extractor().to_string()),
+            ..Default::default()
+        };
+        history.apply_video_info(&video);
+
+        for &progress in &self.script.progress {
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    history.status = DownloadStatus::Cancelled;
+                    return Ok(history);
+                }
+                _ = tokio::time::sleep(Duration::from_millis(self.script.step_delay_ms)) => {}
+            }
+            self.report(DownloadProgress::new(&download_id, DownloadStatus::Downloading, progress));
+        }
+
+        if let ScriptedOutcome::Fail { message } = &self.script.outcome {
+            return Err(anyhow!("{}", message));
+        }
+
+        let relative = match &request.filename {
+            Some(name) => PathBuf::from(format!(
+                "{}.{}",
+                filename::sanitize_file_name(name, self.options.filename_options.ascii_only),
+                request.format
+            )),
+            None => filename::render_filename(
+                &self.options.filename_options,
+                &FilenameContext {
+                    title: video.title,
+                    uploader: video.uploader,
+                    id: video.id,
+                    quality: request.quality.clone(),
+                    ext: request.format.clone(),
+                    ..Default::default()
+                },
+            ),
+        };
+        let path = self.options.output_dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, b"scripted download")?;
+
+        self.report(DownloadProgress::new(&download_id, DownloadStatus::Completed, 100.0));
+        history.status = DownloadStatus::Completed;
+        history.file_path = Some(path.to_string_lossy().to_string());
+        history.output_files = vec![path.to_string_lossy().to_string()];
+        Ok(history)
+    }
+}
@@ -0,0 +1,127 @@
+use crate::fixtures;
+use anyhow::{anyhow, Result};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+// Variables child processes still need: locating the user's profile and temp
+// dirs, locale for yt-dlp's output encoding, proxies and TLS trust stores.
+const PASSTHROUGH_ENV: &[&str] = &[
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "PATHEXT",
+    "TEMP",
+    "TMP",
+    "TMPDIR",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "XDG_CACHE_HOME",
+    "XDG_CONFIG_HOME",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+];
+
+fn child_path() -> String {
+    let current_path = std::env::var("PATH").unwrap_or_default();
+    if cfg!(target_os = "macos") {
+        format!(
+            "{}:/usr/local/bin:/opt/homebrew/bin:/usr/bin:/bin:/opt/local/bin",
+            current_path
+        )
+    } else {
+        current_path
+    }
+}
+
+fn child_env() -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = PASSTHROUGH_ENV
+        .iter()
+        .filter_map(|name| std::env::var(name).ok().map(|value| (name.to_string(), value)))
+        .collect();
+    // The replay stand-in needs to know where its fixtures live.
+    if let Some(dir) = fixtures::replay_dir() {
+        env.push((fixtures::REPLAY_ENV.to_string(), dir.to_string_lossy().to_string()));
+    }
+    env.push(("PATH".to_string(), child_path()));
+    env.push(("PYTHONIOENCODING".to_string(), "utf-8".to_string()));
+    env
+}
+
+/// Builds a command that starts from an empty environment instead of inheriting
+/// everything the app was launched with.
+pub fn clean_command<S: AsRef<OsStr>>(program: S) -> std::process::Command {
+    let mut cmd = std::process::Command::new(program);
+    cmd.env_clear().envs(child_env());
+    cmd
+}
+
+pub fn clean_async_command<S: AsRef<OsStr>>(program: S) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new(program);
+    cmd.env_clear().envs(child_env());
+    cmd
+}
+
+/// Canonicalizes the deepest existing ancestor and re-appends the rest, so
+/// directories that do not exist yet can still be checked.
+fn canonicalize_lenient(path: &Path) -> Result<PathBuf> {
+    if path
+        .components()
+        .any(|c| matches!(c, Component::ParentDir | Component::CurDir))
+    {
+        return Err(anyhow!("Path must not contain relative components: {}", path.display()));
+    }
+
+    let mut existing = path.to_path_buf();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name.to_owned());
+                existing = parent.to_path_buf();
+            }
+            _ => return Err(anyhow!("Invalid path: {}", path.display())),
+        }
+    }
+
+    let mut resolved = existing
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", path.display(), e))?;
+    resolved.extend(missing.iter().rev());
+    Ok(resolved)
+}
+
+pub fn resolve_output_dir(requested: &str, approved_roots: &[PathBuf]) -> Result<PathBuf> {
+    let requested = Path::new(requested.trim());
+    if !requested.is_absolute() {
+        return Err(anyhow!("Output path must be absolute: {}", requested.display()));
+    }
+
+    let resolved = canonicalize_lenient(requested)?;
+    let approved = approved_roots
+        .iter()
+        .filter(|root| root.is_absolute())
+        .filter_map(|root| canonicalize_lenient(root).ok())
+        .any(|root| resolved.starts_with(&root));
+
+    if !approved {
+        return Err(anyhow!(
+            "Output path is outside the approved download folders: {}",
+            resolved.display()
+        ));
+    }
+    Ok(resolved)
+}
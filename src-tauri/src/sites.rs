@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+pub const YOUTUBE_EXTRACTOR: &str = "Youtube";
+
+/// Hosts served by the extractors in `default_sites`, so a URL can be matched
+/// to a site without running yt-dlp. Subdomains match too.
+const EXTRACTOR_HOSTS: &[(&str, &[&str])] = &[
+    (YOUTUBE_EXTRACTOR, &["youtube.com", "youtu.be", "youtube-nocookie.com"]),
+    ("Vimeo", &["vimeo.com"]),
+    ("Soundcloud", &["soundcloud.com"]),
+    ("Bandcamp", &["bandcamp.com"]),
+    ("TwitchVod", &["twitch.tv"]),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteConfig {
+    pub name: String,
+    /// yt-dlp extractor keys handled by this entry, e.g. `Vimeo` or `TwitchVod`.
+    pub extractors: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub referer: Option<String>,
+    #[serde(default)]
+    pub default_format: Option<String>,
+    #[serde(default)]
+    pub default_quality: Option<String>,
+    /// Hosts recognised as this site on top of the built-in ones for its
+    /// extractors, e.g. `example.com`.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl SiteConfig {
+    fn new(name: &str, extractors: &[&str], referer: Option<&str>, default_format: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            extractors: extractors.iter().map(|e| e.to_string()).collect(),
+            enabled: true,
+            referer: referer.map(|r| r.to_string()),
+            default_format: default_format.map(|f| f.to_string()),
+            default_quality: None,
+            hosts: Vec::new(),
+        }
+    }
+
+    pub fn handles(&self, extractor: &str) -> bool {
+        // Extractor names come as either the key ("YoutubeTab") or the
+        // lowercase name with a sub-extractor suffix ("youtube:tab").
+        let normalized = extractor.replace(':', "");
+        self.extractors
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(extractor) || e.eq_ignore_ascii_case(&normalized))
+    }
+
+    pub fn matches_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let builtin = EXTRACTOR_HOSTS
+            .iter()
+            .filter(|(extractor, _)| self.handles(extractor))
+            .flat_map(|(_, hosts)| hosts.iter().copied());
+        self.hosts.iter().map(String::as_str).chain(builtin).any(|pattern| {
+            let pattern = pattern.trim_start_matches("*.").to_ascii_lowercase();
+            host == pattern || host.ends_with(&format!(".{}", pattern))
+        })
+    }
+}
+
+pub fn default_sites() -> Vec<SiteConfig> {
+    vec![
+        SiteConfig::new(
+            "YouTube",
+            &["Youtube", "YoutubeTab"],
+            Some("https://www.youtube.com/"),
+            None,
+        ),
+        SiteConfig::new("Vimeo", &["Vimeo", "VimeoOndemand"], Some("https://vimeo.com/"), None),
+        SiteConfig::new("SoundCloud", &["Soundcloud"], None, Some("mp3")),
+        SiteConfig::new("Bandcamp", &["Bandcamp", "BandcampAlbum"], None, Some("mp3")),
+        SiteConfig::new("Twitch VODs", &["TwitchVod", "TwitchClips"], None, Some("mp4")),
+    ]
+}
+
+pub fn find_site<'a>(sites: &'a [SiteConfig], extractor: &str) -> Option<&'a SiteConfig> {
+    sites.iter().find(|site| site.handles(extractor))
+}
+
+/// The site an http(s) URL belongs to, judged by its host alone.
+pub fn find_site_for_url<'a>(sites: &'a [SiteConfig], url: &str) -> Option<&'a SiteConfig> {
+    let url = url::Url::parse(url.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = url.host_str()?;
+    sites.iter().find(|site| site.matches_host(host))
+}
@@ -0,0 +1,123 @@
+use crate::cache;
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+/// Subfolder of a configured staging path that Snapper stages downloads in.
+/// The configured folder may hold other files, so only this subfolder is ever
+/// cleaned up.
+pub const STAGING_DIR_NAME: &str = "snapper-staging";
+
+/// Marks a staging folder whose files could not be moved to the output folder.
+const KEEP_MARKER: &str = ".snapper-keep";
+
+pub fn default_staging_root() -> PathBuf {
+    cache::cache_root().join("staging")
+}
+
+pub fn staging_root_in(base: &Path) -> PathBuf {
+    base.join(STAGING_DIR_NAME)
+}
+
+pub fn create_staging_dir(root: &Path, download_id: &str) -> Result<PathBuf> {
+    let dir = root.join(download_id);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| anyhow!("Failed to create staging directory {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+/// Keeps a staging folder whose files could not be moved out, since it may
+/// hold the only copy of the download, and adds its location to `error`.
+pub fn keep_staging_dir(dir: &Path, error: anyhow::Error) -> anyhow::Error {
+    if let Err(e) = std::fs::write(dir.join(KEEP_MARKER), b"") {
+        eprintln!("Warning: Failed to mark staging directory {:?} as kept: {}", dir, e);
+    }
+    anyhow!("{} (downloaded files were kept in {})", error, dir.display())
+}
+
+pub fn is_kept(dir: &Path) -> bool {
+    dir.join(KEEP_MARKER).exists()
+}
+
+/// Removes a job's staging folder unless it was kept after a failed move.
+pub fn remove_staging_dir(dir: &Path) {
+    if is_kept(dir) {
+        println!("Keeping staging directory with unmoved files: {}", dir.display());
+        return;
+    }
+    if dir.exists() {
+        if let Err(e) = std::fs::remove_dir_all(dir) {
+            eprintln!("Warning: Failed to remove staging directory {:?}: {}", dir, e);
+        }
+    }
+}
+
+/// Removes what interrupted downloads left in `root`, which must be a folder
+/// only Snapper writes to: the default root or one from `staging_root_in`.
+pub fn cleanup_staging_root(root: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if is_kept(&path) {
+            continue;
+        }
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match result {
+            Ok(_) => removed += 1,
+            Err(e) => eprintln!("Failed to remove leftover staging entry {:?}: {}", path, e),
+        }
+    }
+    removed
+}
+
+pub fn move_file(source: &Path, destination: &Path, overwrite: bool) -> Result<()> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if destination.exists() && !overwrite {
+        return Err(anyhow!("Destination already exists: {}", destination.display()));
+    }
+
+    if std::fs::rename(source, destination).is_ok() {
+        return Ok(());
+    }
+
+    // Rename fails across filesystems; copy next to the destination first so the
+    // final rename stays atomic and a partial copy never appears under the real name.
+    let file_name = destination
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid destination: {}", destination.display()))?;
+    let partial = destination.with_file_name(format!(
+        ".{}.snapper-partial",
+        file_name.to_string_lossy()
+    ));
+
+    if let Err(e) = std::fs::copy(source, &partial) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(anyhow!(
+            "Failed to copy {} to {}: {}",
+            source.display(),
+            destination.display(),
+            e
+        ));
+    }
+
+    if let Err(e) = std::fs::rename(&partial, destination) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(anyhow!("Failed to move file into {}: {}", destination.display(), e));
+    }
+
+    if let Err(e) = std::fs::remove_file(source) {
+        eprintln!("Warning: Failed to remove staged file {:?}: {}", source, e);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use crate::youtube::ErrorCategory;
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DEFAULT_TOP_UPLOADERS: usize = 10;
+/// Longest time series returned, so a wide range at daily resolution stays
+/// cheap to send and draw.
+pub const MAX_SERIES_POINTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsInterval {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsInterval {
+    /// SQL expression giving the local start date of the period containing
+    /// `downloaded_at`, as `YYYY-MM-DD`. Weeks start on Monday.
+    pub(crate) fn sql_period(self) -> &'static str {
+        match self {
+            StatsInterval::Day => "date(downloaded_at / 1000, 'unixepoch', 'localtime')",
+            StatsInterval::Week => {
+                "date(downloaded_at / 1000, 'unixepoch', 'localtime', 'weekday 0', '-6 days')"
+            }
+            StatsInterval::Month => "strftime('%Y-%m-01', downloaded_at / 1000, 'unixepoch', 'localtime')",
+        }
+    }
+
+    /// Start of the period containing `date`, matching `sql_period`.
+    pub fn period_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            StatsInterval::Day => date,
+            StatsInterval::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            StatsInterval::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    fn previous(self, period: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            StatsInterval::Day => period.pred_opt(),
+            StatsInterval::Week => period.checked_sub_signed(Duration::weeks(1)),
+            StatsInterval::Month => period.checked_sub_months(Months::new(1)),
+        }
+    }
+
+    /// Period starts, oldest first, from the one containing `from` to the one
+    /// containing `to`. Longer ranges keep the newest `MAX_SERIES_POINTS`.
+    pub fn periods(self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let first = self.period_start(from);
+        let mut periods = Vec::new();
+        let mut period = Some(self.period_start(to));
+        while let Some(current) = period.filter(|p| *p >= first && periods.len() < MAX_SERIES_POINTS) {
+            periods.push(current);
+            period = self.previous(current);
+        }
+        periods.reverse();
+        periods
+    }
+}
+
+/// Range and shape of `get_download_statistics`. Missing bounds cover the
+/// whole history.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub interval: StatsInterval,
+    pub top_uploaders: Option<usize>,
+}
+
+impl StatsQuery {
+    pub(crate) fn local_date(time: DateTime<Utc>) -> NaiveDate {
+        time.with_timezone(&Local).date_naive()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploaderStats {
+    pub uploader: String,
+    pub downloads: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureStats {
+    pub category: ErrorCategory,
+    pub count: usize,
+}
+
+/// Downloads started in one day, week or month. Bytes and duration count
+/// completed downloads only.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsPoint {
+    pub period: NaiveDate,
+    pub downloads: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub bytes: u64,
+    pub duration_seconds: u64,
+}
+
+impl StatsPoint {
+    pub fn empty(period: NaiveDate) -> Self {
+        Self {
+            period,
+            downloads: 0,
+            completed: 0,
+            failed: 0,
+            bytes: 0,
+            duration_seconds: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownloadStats {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub downloading: usize,
+    pub most_used_format: Option<String>,
+    pub formats_breakdown: HashMap<String, usize>,
+    #[serde(default)]
+    pub total_bytes: u64,
+    #[serde(default)]
+    pub total_duration_seconds: u64,
+    /// Bytes per second over all completed downloads with a recorded speed.
+    #[serde(default)]
+    pub average_speed: Option<f64>,
+    /// Completed downloads as a share of those that completed or failed.
+    #[serde(default)]
+    pub success_rate: Option<f64>,
+    #[serde(default)]
+    pub top_uploaders: Vec<UploaderStats>,
+    #[serde(default)]
+    pub failure_reasons: Vec<FailureStats>,
+    #[serde(default)]
+    pub interval: StatsInterval,
+    #[serde(default)]
+    pub series: Vec<StatsPoint>,
+}
+
+const CSV_HEADER: &str = "period,downloads,completed,failed,bytes,duration_seconds";
+
+/// The time series as CSV, one row per period.
+pub fn series_csv(stats: &DownloadStats) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+    for point in &stats.series {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            point.period.format("%Y-%m-%d"),
+            point.downloads,
+            point.completed,
+            point.failed,
+            point.bytes,
+            point.duration_seconds
+        ));
+    }
+    csv
+}
@@ -0,0 +1,129 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+/// Moves a file to the desktop's trash or recycle bin instead of deleting it.
+pub fn move_to_trash(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Err(anyhow!("File does not exist: {}", path.display()));
+    }
+    platform_trash(path)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn platform_trash(path: &Path) -> Result<()> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))
+        .ok_or_else(|| anyhow!("Cannot locate the home trash directory"))?;
+    move_to_trash_in(path, &data_home.join("Trash")).map(|_| ())
+}
+
+#[cfg(target_os = "macos")]
+fn platform_trash(path: &Path) -> Result<()> {
+    let absolute = std::fs::canonicalize(path)?;
+    let script = format!(
+        "tell application \"Finder\" to delete POSIX file \"{}\"",
+        absolute.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    let output = crate::security::clean_command("osascript")
+        .args(["-e", &script])
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Finder could not move the file to the trash: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+#[cfg(windows)]
+fn platform_trash(path: &Path) -> Result<()> {
+    let absolute = std::fs::canonicalize(path)?;
+    // The path goes in through the environment so it is never parsed as script.
+    let script = "Add-Type -AssemblyName Microsoft.VisualBasic; \
+        [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteFile($env:SNAPPER_TRASH_PATH, 'OnlyErrorDialogs', 'SendToRecycleBin')";
+    let output = crate::security::clean_command("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .env("SNAPPER_TRASH_PATH", absolute.as_os_str())
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Could not move the file to the Recycle Bin: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn platform_trash(_path: &Path) -> Result<()> {
+    Err(anyhow!("Moving files to the trash is not supported on this platform"))
+}
+
+/// Freedesktop.org trash: the file goes to `files/` and a `.trashinfo` record
+/// with its original location goes to `info/`. Returns the trashed path.
+pub fn move_to_trash_in(path: &Path, trash_dir: &Path) -> Result<PathBuf> {
+    let absolute = std::fs::canonicalize(path)?;
+    let file_name = absolute
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file path: {}", path.display()))?
+        .to_string_lossy()
+        .to_string();
+
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    std::fs::create_dir_all(&files_dir)?;
+    std::fs::create_dir_all(&info_dir)?;
+
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem.to_string(), format!(".{}", ext)),
+        _ => (file_name.clone(), String::new()),
+    };
+
+    for attempt in 0..1000 {
+        let name = if attempt == 0 {
+            file_name.clone()
+        } else {
+            format!("{} ({}){}", stem, attempt, extension)
+        };
+        let info_path = info_dir.join(format!("{}.trashinfo", name));
+        // Creating the info file exclusively reserves the name.
+        let mut info = match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let encoded = url::Url::from_file_path(&absolute)
+            .map(|url| url.path().to_string())
+            .unwrap_or_else(|_| absolute.to_string_lossy().to_string());
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encoded,
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+        );
+        std::io::Write::write_all(&mut info, contents.as_bytes())?;
+        drop(info);
+
+        let destination = files_dir.join(&name);
+        if let Err(e) = std::fs::rename(&absolute, &destination) {
+            let _ = std::fs::remove_file(&info_path);
+            return Err(anyhow!(
+                "Could not move {} to the trash: {}",
+                absolute.display(),
+                e
+            ));
+        }
+        return Ok(destination);
+    }
+
+    Err(anyhow!("Too many files named {} in the trash", file_name))
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+const YOUTUBE_HOSTS: &[&str] = &[
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "music.youtube.com",
+    "youtu.be",
+    "www.youtu.be",
+    "youtube-nocookie.com",
+    "www.youtube-nocookie.com",
+];
+
+// Query parameters carried over to canonical watch URLs besides `v` and `t`;
+// everything else (si, feature, pp, utm_*, ...) is tracking or UI state.
+const KEPT_PARAMS: &[&str] = &["list", "index"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlKind {
+    Video,
+    Short,
+    Live,
+    Playlist,
+    Channel,
+    MusicTrack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlAnalysis {
+    pub original_url: String,
+    pub canonical_url: String,
+    pub kind: UrlKind,
+    pub video_id: Option<String>,
+    pub playlist_id: Option<String>,
+    pub start_time: Option<u64>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum UrlError {
+    #[error("Not a valid URL: {0}")]
+    Invalid(String),
+    #[error("Unsupported URL scheme: {0}")]
+    UnsupportedScheme(String),
+    #[error("Unsupported host: {0}")]
+    UnsupportedHost(String),
+    #[error("URL does not point to a video, playlist or channel")]
+    UnrecognizedPath,
+}
+
+pub fn analyze_url(input: &str) -> Result<UrlAnalysis, UrlError> {
+    let input = input.trim();
+    let parsed = Url::parse(input)
+        .or_else(|_| Url::parse(&format!("https://{}", input)))
+        .map_err(|e| UrlError::Invalid(e.to_string()))?;
+
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(UrlError::UnsupportedScheme(parsed.scheme().to_string()));
+    }
+
+    let host = parsed
+        .host_str()
+        .map(|h| h.to_ascii_lowercase())
+        .ok_or_else(|| UrlError::Invalid(input.to_string()))?;
+    if !YOUTUBE_HOSTS.contains(&host.as_str()) {
+        return Err(UrlError::UnsupportedHost(host));
+    }
+
+    let segments: Vec<&str> = parsed
+        .path_segments()
+        .map(|s| s.filter(|part| !part.is_empty()).collect())
+        .unwrap_or_default();
+    let query = |name: &str| {
+        parsed
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .filter(|value| !value.is_empty())
+    };
+
+    let playlist_id = query("list").filter(|id| is_valid_id(id));
+    let start_time = query("t")
+        .or_else(|| query("start"))
+        .or_else(|| {
+            parsed
+                .fragment()
+                .and_then(|f| f.strip_prefix("t="))
+                .map(|t| t.to_string())
+        })
+        .and_then(|t| parse_start_time(&t));
+
+    let (kind, video_id) = if host.ends_with("youtu.be") {
+        (UrlKind::Video, segments.first().map(|s| s.to_string()))
+    } else {
+        match segments.as_slice() {
+            ["watch"] => {
+                let kind = if host == "music.youtube.com" {
+                    UrlKind::MusicTrack
+                } else {
+                    UrlKind::Video
+                };
+                (kind, query("v"))
+            }
+            ["shorts", id, ..] => (UrlKind::Short, Some(id.to_string())),
+            ["live", id, ..] => (UrlKind::Live, Some(id.to_string())),
+            ["embed", "videoseries", ..] => (UrlKind::Playlist, None),
+            ["embed", id, ..] | ["v", id, ..] => (UrlKind::Video, Some(id.to_string())),
+            ["playlist"] => (UrlKind::Playlist, None),
+            ["channel", _, ..] | ["c", _, ..] | ["user", _, ..] => (UrlKind::Channel, None),
+            [handle, ..] if handle.starts_with('@') => (UrlKind::Channel, None),
+            _ => return Err(UrlError::UnrecognizedPath),
+        }
+    };
+
+    if let Some(id) = &video_id {
+        if !is_valid_id(id) {
+            return Err(UrlError::UnrecognizedPath);
+        }
+    }
+    if kind == UrlKind::Playlist && playlist_id.is_none() {
+        return Err(UrlError::UnrecognizedPath);
+    }
+    if matches!(kind, UrlKind::Video | UrlKind::MusicTrack) && video_id.is_none() {
+        // A bare watch page with only a playlist attached is still a playlist.
+        return match playlist_id {
+            Some(playlist_id) => Ok(UrlAnalysis {
+                original_url: input.to_string(),
+                canonical_url: canonical_playlist_url(&playlist_id),
+                kind: UrlKind::Playlist,
+                video_id: None,
+                playlist_id: Some(playlist_id),
+                start_time: None,
+            }),
+            None => Err(UrlError::UnrecognizedPath),
+        };
+    }
+
+    let canonical_url = match (kind, &video_id) {
+        (UrlKind::Playlist, _) => canonical_playlist_url(playlist_id.as_deref().unwrap_or_default()),
+        (UrlKind::Channel, _) => format!("https://www.youtube.com/{}", segments.join("/")),
+        (UrlKind::Short, Some(id)) => format!("https://www.youtube.com/shorts/{}", id),
+        (_, Some(id)) => {
+            let base = if kind == UrlKind::MusicTrack {
+                "https://music.youtube.com/watch"
+            } else {
+                "https://www.youtube.com/watch"
+            };
+            let mut canonical = Url::parse(base).expect("static URL is valid");
+            {
+                let mut pairs = canonical.query_pairs_mut();
+                pairs.append_pair("v", id);
+                for (key, value) in parsed.query_pairs() {
+                    if KEPT_PARAMS.contains(&key.as_ref()) {
+                        pairs.append_pair(&key, &value);
+                    }
+                }
+                if let Some(start) = start_time {
+                    pairs.append_pair("t", &format!("{}s", start));
+                }
+            }
+            canonical.to_string()
+        }
+        (_, None) => return Err(UrlError::UnrecognizedPath),
+    };
+
+    Ok(UrlAnalysis {
+        original_url: input.to_string(),
+        canonical_url,
+        kind,
+        video_id,
+        playlist_id,
+        start_time,
+    })
+}
+
+pub fn is_supported_url(input: &str) -> bool {
+    analyze_url(input).is_ok()
+}
+
+fn canonical_playlist_url(playlist_id: &str) -> String {
+    format!("https://www.youtube.com/playlist?list={}", playlist_id)
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Parses `90`, `90s`, `1m30s` and `1h2m3s` style offsets into seconds.
+pub fn parse_start_time(value: &str) -> Option<u64> {
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds);
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let amount: u64 = number.parse().ok()?;
+        number.clear();
+        let seconds = match c {
+            'h' => amount.checked_mul(3600)?,
+            'm' => amount.checked_mul(60)?,
+            's' => amount,
+            _ => return None,
+        };
+        total = total.checked_add(seconds)?;
+    }
+
+    number.is_empty().then_some(total)
+}
@@ -0,0 +1,185 @@
+use crate::youtube::{DownloadHistory, DownloadStatus};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::time::Duration;
+
+pub const SIGNATURE_HEADER: &str = "X-Snapper-Signature";
+pub const EVENT_HEADER: &str = "X-Snapper-Event";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    Started,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default = "default_webhook_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+}
+
+// Settings are logged on load and save, so the signing secret is redacted.
+impl fmt::Debug for WebhookConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookConfig")
+            .field("name", &self.name)
+            .field("url", &self.url)
+            .field("events", &self.events)
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .field("enabled", &self.enabled)
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+fn default_webhook_enabled() -> bool {
+    true
+}
+
+fn default_webhook_max_retries() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    pub sent_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub download: DownloadHistory,
+    /// Failure text for `Failed` events. Not named `error`, which the
+    /// flattened entry already uses for its structured failure.
+    pub error_message: Option<String>,
+}
+
+impl WebhookEvent {
+    pub fn for_status(status: &DownloadStatus) -> Option<Self> {
+        match status {
+            DownloadStatus::Downloading => Some(WebhookEvent::Started),
+            DownloadStatus::Completed => Some(WebhookEvent::Completed),
+            DownloadStatus::Failed => Some(WebhookEvent::Failed),
+            DownloadStatus::Cancelled => Some(WebhookEvent::Cancelled),
+            DownloadStatus::Pending
+            | DownloadStatus::Skipped
+            | DownloadStatus::Paused
+            | DownloadStatus::Missing => None,
+        }
+    }
+}
+
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub fn dispatch(
+    webhooks: &[WebhookConfig],
+    event: WebhookEvent,
+    download: &DownloadHistory,
+    error: Option<String>,
+) {
+    let targets: Vec<WebhookConfig> = webhooks
+        .iter()
+        .filter(|w| w.enabled && w.events.contains(&event))
+        .cloned()
+        .collect();
+
+    if targets.is_empty() {
+        return;
+    }
+
+    let payload = WebhookPayload {
+        event,
+        sent_at: Utc::now(),
+        download: download.clone(),
+        error_message: error,
+    };
+
+    let body = match serde_json::to_vec(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Failed to serialize webhook payload: {}", e);
+            return;
+        }
+    };
+
+    for webhook in targets {
+        let body = body.clone();
+        tokio::spawn(async move {
+            if let Err(e) = deliver(&webhook, event, &body).await {
+                eprintln!("Webhook '{}' delivery failed: {}", webhook.name, e);
+            }
+        });
+    }
+}
+
+pub async fn deliver(webhook: &WebhookConfig, event: WebhookEvent, body: &[u8]) -> Result<()> {
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let signature = webhook
+        .secret
+        .as_deref()
+        .filter(|s| !s.is_empty())
+        .map(|secret| sign_payload(secret, body));
+
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        let mut request = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, format!("{:?}", event))
+            .body(body.to_vec());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                println!("Webhook '{}' delivered ({:?})", webhook.name, event);
+                return Ok(());
+            }
+            Ok(response) => {
+                let status = response.status();
+                if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    return Err(anyhow!("Endpoint rejected webhook with {}", status));
+                }
+                anyhow!("Endpoint responded with {}", status)
+            }
+            Err(e) => anyhow!("Request failed: {}", e),
+        };
+
+        if attempt >= webhook.max_retries {
+            return Err(anyhow!("{} (after {} attempts)", error, attempt + 1));
+        }
+
+        eprintln!(
+            "Webhook '{}' attempt {} failed: {}, retrying in {:?}",
+            webhook.name,
+            attempt + 1,
+            error,
+            backoff
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
@@ -1,7 +1,30 @@
+use crate::backend::{BackendOptions, Binaries, CancellationToken, FormatInfo, MediaBackend};
+use crate::cache::{self, JobCache};
+use crate::diskspace::{self, DiskSpaceOptions, DiskSpacePolicy, SizeEstimate};
+use crate::direct::{DirectDownloader, PartialFile, RemoteMedia, DIRECT_EXTRACTOR};
+use crate::filename::{self, FilenameContext, FilenameOptions};
+use crate::fixtures;
+use crate::hooks::HookResult;
+use crate::metadata_cache::{self, CachedMetadata};
+use crate::security;
+use crate::sites::{self, SiteConfig};
+use crate::staging;
+use crate::urls;
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+// A download paused for low disk space fails once it has waited this long.
+const MAX_DISK_PAUSE: Duration = Duration::from_secs(15 * 60);
+const PROGRESS_PREFIX: &str = "SNAPPER_PROGRESS ";
+pub(crate) const MAX_RENAME_ATTEMPTS: u32 = 1000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoInfo {
@@ -13,7 +36,92 @@ pub struct VideoInfo {
     pub uploader: Option<String>,
     pub upload_date: Option<String>,
     pub view_count: Option<u64>,
+    pub playlist_title: Option<String>,
+    pub playlist_index: Option<u64>,
     pub available_formats: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    #[serde(default)]
+    pub channel_url: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub like_count: Option<u64>,
+    #[serde(default)]
+    pub age_limit: Option<u64>,
+    #[serde(default)]
+    pub availability: Option<String>,
+    #[serde(default)]
+    pub live_status: LiveStatus,
+    #[serde(default)]
+    pub scheduled_start: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub aspect_ratio: Option<f64>,
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+    #[serde(default)]
+    pub subtitle_languages: Vec<String>,
+    #[serde(default)]
+    pub automatic_caption_languages: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveStatus {
+    #[default]
+    NotLive,
+    IsLive,
+    WasLive,
+    IsUpcoming,
+    PostLive,
+}
+
+impl LiveStatus {
+    fn from_video_data(video_data: &serde_json::Value) -> Self {
+        match video_data["live_status"].as_str() {
+            Some("is_live") => LiveStatus::IsLive,
+            Some("was_live") => LiveStatus::WasLive,
+            Some("is_upcoming") => LiveStatus::IsUpcoming,
+            Some("post_live") => LiveStatus::PostLive,
+            Some(_) => LiveStatus::NotLive,
+            // Older yt-dlp versions only report the boolean flags.
+            None if video_data["is_live"].as_bool() == Some(true) => LiveStatus::IsLive,
+            None if video_data["was_live"].as_bool() == Some(true) => LiveStatus::WasLive,
+            None => LiveStatus::NotLive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+}
+
+fn string_list(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn object_keys(value: &serde_json::Value) -> Vec<String> {
+    let mut keys: Vec<String> = value
+        .as_object()
+        .map(|map| map.keys().cloned().collect())
+        .unwrap_or_default();
+    keys.sort();
+    keys
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +131,18 @@ pub struct DownloadRequest {
     pub quality: String,
     pub output_path: String,
     pub filename: Option<String>,
+    #[serde(default)]
+    pub pipeline: Option<String>,
+    #[serde(default)]
+    pub collision_policy: Option<CollisionPolicy>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollisionPolicy {
+    Overwrite,
+    Skip,
+    #[default]
+    Rename,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,18 +154,168 @@ pub struct DownloadProgress {
     pub eta: Option<String>,
     pub filename: Option<String>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub warning: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl DownloadProgress {
+    pub fn new(id: &str, status: DownloadStatus, progress: f64) -> Self {
+        Self {
+            id: id.to_string(),
+            status,
+            progress,
+            speed: None,
+            eta: None,
+            filename: None,
+            error: None,
+            warning: None,
+        }
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(DownloadProgress) + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("Not enough disk space at {path}: {required} bytes required, {available} bytes available")]
+    DiskFull {
+        path: String,
+        required: u64,
+        available: u64,
+    },
+    #[error("Download cancelled")]
+    Cancelled,
+}
+
+impl DownloadError {
+    pub fn is_cancellation(error: &anyhow::Error) -> bool {
+        matches!(error.downcast_ref::<DownloadError>(), Some(DownloadError::Cancelled))
+    }
+}
+
+/// Broad kind of a failed download, used to group failures in statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Network,
+    Unavailable,
+    Restricted,
+    FormatUnavailable,
+    DiskSpace,
+    PostProcessing,
+    Unsupported,
+    #[default]
+    Other,
+}
+
+impl ErrorCategory {
+    /// Guesses the category from yt-dlp's or our own error text.
+    pub fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+
+        if mentions(&["not enough disk space", "no space left"]) {
+            ErrorCategory::DiskSpace
+        } else if mentions(&["requested format is not available", "no video formats found", "unsupported format"]) {
+            ErrorCategory::FormatUnavailable
+        } else if mentions(&["unsupported url", "only supports", "is disabled", "is not enabled"]) {
+            ErrorCategory::Unsupported
+        } else if mentions(&[
+            "sign in",
+            "log in",
+            "login",
+            "private video",
+            "members-only",
+            "age-restricted",
+            "confirm your age",
+            "not available in your country",
+            "geo restricted",
+            "geo-restricted",
+        ]) {
+            ErrorCategory::Restricted
+        } else if mentions(&[
+            "video unavailable",
+            "has been removed",
+            "does not exist",
+            "http error 404",
+            "http error 410",
+            "has been terminated",
+        ]) {
+            ErrorCategory::Unavailable
+        } else if mentions(&["postprocessing", "ffmpeg", "ffprobe", "post-process"]) {
+            ErrorCategory::PostProcessing
+        } else if mentions(&[
+            "timed out",
+            "connection",
+            "network",
+            "unable to download",
+            "http error 5",
+            "name resolution",
+            "ssl",
+            "server returned",
+        ]) {
+            ErrorCategory::Network
+        } else {
+            ErrorCategory::Other
+        }
+    }
+}
+
+const MAX_ERROR_MESSAGE_CHARS: usize = 2000;
+
+/// Why a download failed, as stored in history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadFailure {
+    pub category: ErrorCategory,
+    pub message: String,
+}
+
+impl DownloadFailure {
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<DownloadError>() {
+            Some(DownloadError::DiskFull { .. }) => Self {
+                category: ErrorCategory::DiskSpace,
+                message: error.to_string(),
+            },
+            _ => Self::from_message(&error.to_string()),
+        }
+    }
+
+    /// Keeps only yt-dlp's `ERROR:` lines when there are any, since the rest
+    /// of its stderr is warnings and progress noise.
+    pub fn from_message(message: &str) -> Self {
+        let errors: Vec<&str> = message
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.contains("ERROR:"))
+            .collect();
+        let message = if errors.is_empty() {
+            message.trim().to_string()
+        } else {
+            errors.join("\n")
+        };
+        Self {
+            category: ErrorCategory::classify(&message),
+            message: message.chars().take(MAX_ERROR_MESSAGE_CHARS).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub enum DownloadStatus {
+    #[default]
     Pending,
     Downloading,
     Completed,
     Failed,
     Cancelled,
+    Skipped,
+    Paused,
+    /// The file was completed once but is no longer where history says.
+    Missing,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DownloadHistory {
     pub id: String,
     pub title: String,
@@ -55,10 +325,212 @@ pub struct DownloadHistory {
     pub file_path: Option<String>,
     pub format: String,
     pub quality: String,
+    #[serde(default)]
+    pub output_files: Vec<String>,
+    #[serde(default)]
+    pub hook_results: Vec<HookResult>,
+    #[serde(default)]
+    pub extractor: Option<String>,
+    #[serde(default)]
+    pub file_size: Option<u64>,
+    /// `reconcile::fingerprint` of the file, used to find it again if moved.
+    #[serde(default)]
+    pub file_hash: Option<String>,
+    /// Status the entry had before verification marked it `Missing`, put
+    /// back when the file turns up again.
+    #[serde(default)]
+    pub status_before_missing: Option<DownloadStatus>,
+    #[serde(default)]
+    pub video_id: Option<String>,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    /// Media duration in seconds.
+    #[serde(default)]
+    pub duration: Option<u64>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    /// Resolution and codecs of the file yt-dlp actually produced, which may
+    /// differ from the requested quality.
+    #[serde(default)]
+    pub resolution: Option<String>,
+    #[serde(default)]
+    pub video_codec: Option<String>,
+    #[serde(default)]
+    pub audio_codec: Option<String>,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Bytes per second from start to finish.
+    #[serde(default)]
+    pub average_speed: Option<f64>,
+    /// How many times this download has been started.
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub error: Option<DownloadFailure>,
+    #[serde(default)]
+    pub yt_dlp_version: Option<String>,
+}
+
+impl DownloadHistory {
+    /// Copies the title and media details from probed metadata.
+    pub fn apply_video_info(&mut self, info: &VideoInfo) {
+        self.title = info.title.clone();
+        self.video_id = Some(info.id.clone()).filter(|id| id != "unknown");
+        self.uploader = info.uploader.clone();
+        self.duration = info.duration;
+        self.thumbnail = info.thumbnail.clone();
+    }
+
+    pub fn apply_media_details(&mut self, details: &MediaDetails) {
+        self.resolution = details.resolution.clone();
+        self.video_codec = details.video_codec.clone();
+        self.audio_codec = details.audio_codec.clone();
+    }
+
+    /// Stamps the finish time and fills in the file size and, for completed
+    /// downloads, the average speed since `started_at`.
+    pub fn record_finish(&mut self) {
+        let finished_at = Utc::now();
+        self.finished_at = Some(finished_at);
+        if !matches!(self.status, DownloadStatus::Completed | DownloadStatus::Skipped) {
+            return;
+        }
+
+        let size = self
+            .file_path
+            .as_deref()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len());
+        if size.is_some() && size != self.file_size {
+            self.file_size = size;
+            self.file_hash = None;
+        }
+
+        if let (DownloadStatus::Completed, Some(size), Some(started_at)) =
+            (&self.status, self.file_size, self.started_at)
+        {
+            let seconds = (finished_at - started_at).num_milliseconds() as f64 / 1000.0;
+            if seconds > 0.0 {
+                self.average_speed = Some(size as f64 / seconds);
+            }
+        }
+    }
+}
+
+/// Resolution and codecs reported for a finished download.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaDetails {
+    pub resolution: Option<String>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+}
+
+impl MediaDetails {
+    pub fn from_json(value: &serde_json::Value) -> Self {
+        let text = |key: &str| {
+            value[key]
+                .as_str()
+                .filter(|s| !s.is_empty() && *s != "none" && *s != "NA")
+                .map(|s| s.to_string())
+        };
+        Self {
+            resolution: text("resolution").filter(|r| r != "audio only"),
+            video_codec: text("vcodec"),
+            audio_codec: text("acodec"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOutput {
+    pub file_path: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub intermediates: Vec<PathBuf>,
+    pub media: MediaDetails,
+}
+
+const REPORT_FILEPATH: &str = "SNAPPER_FILEPATH ";
+const REPORT_MOVED: &str = "SNAPPER_MOVED ";
+const REPORT_MERGED: &str = "SNAPPER_MERGED ";
+const REPORT_MEDIA: &str = "SNAPPER_MEDIA ";
+
+pub fn parse_output_report(report: &str) -> Result<DownloadOutput> {
+    let mut output = DownloadOutput::default();
+    let mut main_path: Option<PathBuf> = None;
+    let mut final_paths: Vec<PathBuf> = Vec::new();
+
+    for line in report.lines() {
+        if let Some(path) = line.strip_prefix(REPORT_FILEPATH) {
+            let path = path.trim();
+            if !path.is_empty() && path != "NA" {
+                main_path.get_or_insert_with(|| PathBuf::from(path));
+                final_paths.push(PathBuf::from(path));
+            }
+        } else if let Some(json) = line.strip_prefix(REPORT_MOVED) {
+            if let Ok(serde_json::Value::Object(moved)) = serde_json::from_str(json.trim()) {
+                final_paths.extend(moved.values().filter_map(|v| v.as_str()).map(PathBuf::from));
+            }
+        } else if let Some(json) = line.strip_prefix(REPORT_MEDIA) {
+            if let Ok(media) = serde_json::from_str::<serde_json::Value>(json.trim()) {
+                output.media = MediaDetails::from_json(&media);
+            }
+        } else if let Some(json) = line.strip_prefix(REPORT_MERGED) {
+            if let Ok(serde_json::Value::Array(merged)) = serde_json::from_str(json.trim()) {
+                output
+                    .intermediates
+                    .extend(merged.iter().filter_map(|v| v.as_str()).map(PathBuf::from));
+            }
+        }
+    }
+
+    for path in final_paths {
+        if !output.files.contains(&path) {
+            output.files.push(path);
+        }
+    }
+    output.intermediates.retain(|p| !output.files.contains(p));
+    output.file_path = main_path.ok_or_else(|| anyhow!("yt-dlp did not report an output file"))?;
+
+    Ok(output)
+}
+
+struct CliDownload<'a> {
+    url: &'a str,
+    filename: &'a str,
+    format: &'a str,
+    quality: &'a str,
+    work_dir: &'a Path,
+    cache_dir: &'a Path,
+    download_id: &'a str,
+    info_json: Option<&'a Path>,
+    referer: Option<&'a str>,
+    cancel: &'a CancellationToken,
+}
+
+/// Low-disk state of a running yt-dlp process between checks.
+#[derive(Default)]
+struct DiskMonitor {
+    paused_since: Option<Instant>,
+    warned: bool,
+}
+
+pub(crate) enum CollisionResolution {
+    Proceed(String),
+    Existing(PathBuf),
 }
 
 pub struct YouTubeDownloader {
     _output_dir: PathBuf,
+    filename_options: FilenameOptions,
+    staging_root: PathBuf,
+    disk_space: DiskSpaceOptions,
+    progress_callback: Option<ProgressCallback>,
+    metadata_ttl: Duration,
+    sites: Vec<SiteConfig>,
+    binaries: Option<Binaries>,
+    direct_downloads: bool,
 }
 
 impl YouTubeDownloader {
@@ -66,12 +538,125 @@ impl YouTubeDownloader {
         Self::find_yt_dlp_path()?;
         Self::find_ffmpeg_path()?;
 
-        Ok(Self {
+        Ok(Self::unresolved(output_dir))
+    }
+
+    fn unresolved(output_dir: PathBuf) -> Self {
+        Self {
             _output_dir: output_dir,
+            filename_options: FilenameOptions::default(),
+            staging_root: staging::default_staging_root(),
+            disk_space: DiskSpaceOptions::default(),
+            progress_callback: None,
+            metadata_ttl: metadata_cache::DEFAULT_METADATA_TTL,
+            sites: sites::default_sites(),
+            binaries: None,
+            direct_downloads: false,
+        }
+    }
+
+    pub fn from_options(options: BackendOptions) -> Result<Self> {
+        let downloader = match options.binaries {
+            Some(binaries) => Self::with_binaries(options.output_dir, binaries),
+            None => Self::new(options.output_dir)?,
+        };
+        let downloader = downloader
+            .with_filename_options(options.filename_options)
+            .with_staging_root(options.staging_root)
+            .with_disk_space_options(options.disk_space)
+            .with_metadata_ttl(options.metadata_ttl)
+            .with_sites(options.sites)
+            .with_direct_downloads(options.direct_downloads);
+        Ok(match options.progress_callback {
+            Some(callback) => downloader.with_progress_callback(callback),
+            None => downloader,
         })
     }
 
-    fn find_yt_dlp_path() -> Result<PathBuf> {
+    /// Builds a downloader around already-resolved binaries, skipping the
+    /// lookup `new` performs.
+    pub fn with_binaries(output_dir: PathBuf, binaries: Binaries) -> Self {
+        Self {
+            binaries: Some(binaries),
+            ..Self::unresolved(output_dir)
+        }
+    }
+
+    fn yt_dlp_binary(&self) -> Result<PathBuf> {
+        match &self.binaries {
+            Some(binaries) => Ok(binaries.yt_dlp.clone()),
+            None => Self::find_yt_dlp_path(),
+        }
+    }
+
+    fn yt_dlp_version(&self) -> Option<String> {
+        match &self.binaries {
+            Some(binaries) => binaries.yt_dlp_version.clone(),
+            None => Self::query_yt_dlp_version(&self.yt_dlp_binary().ok()?),
+        }
+    }
+
+    pub fn query_yt_dlp_version(yt_dlp_path: &Path) -> Option<String> {
+        let output = security::clean_command(yt_dlp_path).arg("--version").output().ok()?;
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !version.is_empty()).then_some(version)
+    }
+
+    fn ffmpeg_binary(&self) -> Result<PathBuf> {
+        match &self.binaries {
+            Some(binaries) => Ok(binaries.ffmpeg.clone()),
+            None => Self::find_ffmpeg_path(),
+        }
+    }
+
+    pub fn with_sites(mut self, sites: Vec<SiteConfig>) -> Self {
+        self.sites = sites;
+        self
+    }
+
+    pub fn with_direct_downloads(mut self, enabled: bool) -> Self {
+        self.direct_downloads = enabled;
+        self
+    }
+
+    pub fn with_metadata_ttl(mut self, ttl: Duration) -> Self {
+        self.metadata_ttl = ttl;
+        self
+    }
+
+    pub fn with_disk_space_options(mut self, options: DiskSpaceOptions) -> Self {
+        self.disk_space = options;
+        self
+    }
+
+    pub fn with_progress_callback(mut self, callback: ProgressCallback) -> Self {
+        self.progress_callback = Some(callback);
+        self
+    }
+
+    fn report_progress(&self, progress: DownloadProgress) {
+        if let Some(callback) = &self.progress_callback {
+            callback(progress);
+        }
+    }
+
+    pub fn with_staging_root(mut self, staging_root: PathBuf) -> Self {
+        self.staging_root = staging_root;
+        self
+    }
+
+    pub fn with_filename_options(mut self, options: FilenameOptions) -> Self {
+        self.filename_options = options;
+        self
+    }
+
+    pub(crate) fn find_yt_dlp_path() -> Result<PathBuf> {
+        if fixtures::replay_dir().is_some() {
+            let replay_path = fixtures::replay_binary()?;
+            println!("Replaying yt-dlp fixtures with: {}", replay_path.display());
+            return Ok(replay_path);
+        }
+
         let embedded_path = Self::get_embedded_binary_path("yt-dlp")?;
         if embedded_path.exists() {
             println!("Using embedded yt-dlp: {}", embedded_path.display());
@@ -103,7 +688,12 @@ impl YouTubeDownloader {
         Err(anyhow!("yt-dlp not found. Please install yt-dlp or place the binary in the application directory."))
     }
 
-    fn find_ffmpeg_path() -> Result<PathBuf> {
+    pub(crate) fn find_ffmpeg_path() -> Result<PathBuf> {
+        // Replayed yt-dlp calls never start ffmpeg, so the stand-in fills in.
+        if fixtures::replay_dir().is_some() {
+            return fixtures::replay_binary();
+        }
+
         let embedded_path = Self::get_embedded_binary_path("ffmpeg")?;
         if embedded_path.exists() {
             println!("Using embedded ffmpeg: {}", embedded_path.display());
@@ -246,12 +836,30 @@ impl YouTubeDownloader {
         }
     }
 
-    pub async fn get_video_info(&self, url: &str) -> Result<VideoInfo> {
-        let yt_dlp_path = Self::find_yt_dlp_path()?;
-        let cache_dir = self.get_cache_dir()?;
-        let output = std::process::Command::new(&yt_dlp_path)
-            .args(["--dump-json", "--no-playlist", "--cache-dir", &cache_dir, url])
-            .output()?;
+    pub async fn get_video_info(&self, url: &str, refresh: bool) -> Result<VideoInfo> {
+        let metadata = self.video_metadata(url, refresh).await?;
+        Ok(Self::parse_video_info(url, &metadata.data))
+    }
+
+    async fn video_metadata(&self, url: &str, refresh: bool) -> Result<CachedMetadata> {
+        if !refresh {
+            if let Some(cached) = metadata_cache::get(url, self.metadata_ttl) {
+                println!("Using cached metadata for {}", url);
+                return Ok(cached);
+            }
+        }
+
+        let video_data = self.fetch_video_json(url).await?;
+        metadata_cache::put(url, video_data)
+    }
+
+    async fn fetch_video_json(&self, url: &str) -> Result<serde_json::Value> {
+        let yt_dlp_path = self.yt_dlp_binary()?;
+        let cache_dir = cache::yt_dlp_cache_dir()?.to_string_lossy().to_string();
+        let mut cmd = security::clean_command(&yt_dlp_path);
+        cmd.args(["--dump-json", "--no-playlist", "--cache-dir", &cache_dir, "--", url]);
+        let output = cmd.output()?;
+        fixtures::record(&fixtures::command_args(&cmd), &output);
 
         if !output.status.success() {
             let error = String::from_utf8_lossy(&output.stderr);
@@ -259,8 +867,10 @@ impl YouTubeDownloader {
         }
 
         let json_str = String::from_utf8(output.stdout)?;
-        let video_data: serde_json::Value = serde_json::from_str(&json_str)?;
+        Ok(serde_json::from_str(&json_str)?)
+    }
 
+    pub fn parse_video_info(url: &str, video_data: &serde_json::Value) -> VideoInfo {
         let available_formats = video_data["formats"]
             .as_array()
             .unwrap_or(&vec![])
@@ -270,7 +880,17 @@ impl YouTubeDownloader {
             .into_iter()
             .collect();
 
-        let video_info = VideoInfo {
+        let live_status = LiveStatus::from_video_data(video_data);
+        // Finished streams and premieres keep a release timestamp in the past.
+        let scheduled_start = if live_status == LiveStatus::IsUpcoming {
+            video_data["release_timestamp"]
+                .as_i64()
+                .and_then(|ts| DateTime::from_timestamp(ts, 0))
+        } else {
+            None
+        };
+
+        VideoInfo {
             id: video_data["id"].as_str().unwrap_or("unknown").to_string(),
             title: video_data["title"]
                 .as_str()
@@ -282,107 +902,346 @@ impl YouTubeDownloader {
             uploader: video_data["uploader"].as_str().map(|s| s.to_string()),
             upload_date: video_data["upload_date"].as_str().map(|s| s.to_string()),
             view_count: video_data["view_count"].as_u64(),
+            playlist_title: video_data["playlist_title"].as_str().map(|s| s.to_string()),
+            playlist_index: video_data["playlist_index"].as_u64(),
             available_formats,
-        };
-
-        Ok(video_info)
+            description: video_data["description"].as_str().map(|s| s.to_string()),
+            channel_id: video_data["channel_id"].as_str().map(|s| s.to_string()),
+            channel_url: video_data["channel_url"].as_str().map(|s| s.to_string()),
+            tags: string_list(&video_data["tags"]),
+            categories: string_list(&video_data["categories"]),
+            like_count: video_data["like_count"].as_u64(),
+            age_limit: video_data["age_limit"].as_u64(),
+            availability: video_data["availability"].as_str().map(|s| s.to_string()),
+            live_status,
+            scheduled_start,
+            aspect_ratio: video_data["aspect_ratio"].as_f64().or_else(|| {
+                let width = video_data["width"].as_f64()?;
+                let height = video_data["height"].as_f64().filter(|h| *h > 0.0)?;
+                Some((width / height * 100.0).round() / 100.0)
+            }),
+            chapters: video_data["chapters"]
+                .as_array()
+                .map(|chapters| {
+                    chapters
+                        .iter()
+                        .filter_map(|c| {
+                            Some(Chapter {
+                                title: c["title"].as_str().unwrap_or_default().to_string(),
+                                start_time: c["start_time"].as_f64()?,
+                                end_time: c["end_time"].as_f64(),
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            subtitle_languages: object_keys(&video_data["subtitles"]),
+            automatic_caption_languages: object_keys(&video_data["automatic_captions"]),
+        }
     }
 
-    pub async fn download_video(&self, request: DownloadRequest, download_id: String) -> Result<DownloadHistory> {
+    pub async fn download_video(
+        &self,
+        mut request: DownloadRequest,
+        download_id: String,
+        cancel: CancellationToken,
+    ) -> Result<DownloadHistory> {
+
+        if self.direct_downloads && !urls::is_supported_url(&request.url) {
+            match self.direct_downloader()?.probe(&request.url).await {
+                Ok(Some(media)) => {
+                    return self.download_direct(request, download_id, media, &cancel).await
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Direct media probe failed, using yt-dlp: {}", e),
+            }
+        }
 
-        let video_info = self.get_video_info(&request.url).await?;
+        let metadata = self.video_metadata(&request.url, false).await?;
+        let video_data = metadata.data.as_ref();
+        let video_info = Self::parse_video_info(&request.url, video_data);
 
-        let clean_title = video_info
-            .title
-            .chars()
-            .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-' || *c == '_' || *c == '.')
-            .collect::<String>()
-            .replace("  ", " ")
-            .trim()
-            .to_string();
+        let extractor = Self::extractor_key(video_data)
+            .unwrap_or_else(|| sites::YOUTUBE_EXTRACTOR.to_string());
+        let site = self.enabled_site(&extractor)?;
+        if request.format.is_empty() || request.format == "auto" {
+            request.format = site.default_format.clone().unwrap_or_else(|| "mp4".to_string());
+        }
+        if request.quality.is_empty() || request.quality == "auto" {
+            request.quality = site.default_quality.clone().unwrap_or_else(|| "high".to_string());
+        }
 
-        let clean_title = if clean_title.len() > 100 {
-            clean_title.chars().take(100).collect()
-        } else {
-            clean_title
+        let filename = match &request.filename {
+            Some(filename) => PathBuf::from(filename::sanitize_file_name(
+                filename,
+                self.filename_options.ascii_only,
+            )),
+            None => filename::render_filename(
+                &self.filename_options,
+                &FilenameContext {
+                    title: video_info.title.clone(),
+                    uploader: video_info.uploader.clone(),
+                    upload_date: video_info.upload_date.clone(),
+                    id: video_info.id.clone(),
+                    playlist_title: video_info.playlist_title.clone(),
+                    playlist_index: video_info.playlist_index,
+                    quality: request.quality.clone(),
+                    ext: request.format.clone(),
+                },
+            ),
+        };
+        let policy = request.collision_policy.unwrap_or_default();
+        let filename = match self.resolve_collision(&filename.to_string_lossy(), &request.format, policy)? {
+            CollisionResolution::Proceed(filename) => filename,
+            CollisionResolution::Existing(existing) => {
+                let mut history = Self::skipped_history(
+                    download_id,
+                    video_info.title.clone(),
+                    request,
+                    &existing,
+                    extractor,
+                );
+                history.apply_video_info(&video_info);
+                return Ok(history);
+            }
         };
-
-        let filename = request
-            .filename
-            .unwrap_or_else(|| format!("{}.{}", clean_title, request.format));
 
         println!("Downloading: {} as {}", video_info.title, filename);
 
-        let result = self
-            .download_with_cli(&request.url, &filename, &request.format, &request.quality)
-            .await;
-
-        let history = match result {
-            Ok(file_path) => DownloadHistory {
-                id: download_id,
-                title: video_info.title,
-                url: request.url,
-                status: DownloadStatus::Completed,
-                downloaded_at: Utc::now(),
-                file_path: Some(file_path.to_string_lossy().to_string()),
-                format: request.format,
-                quality: request.quality,
-            },
-            Err(_e) => DownloadHistory {
-                id: download_id,
-                title: video_info.title,
-                url: request.url,
-                status: DownloadStatus::Failed,
-                downloaded_at: Utc::now(),
-                file_path: None,
-                format: request.format,
-                quality: request.quality,
-            },
+        self.check_disk_space(video_data, &request, &download_id)?;
+
+        let job_cache = JobCache::create(&download_id)?;
+        let staging_dir = staging::create_staging_dir(&self.staging_root, &download_id)?;
+        let job = CliDownload {
+            url: &request.url,
+            filename: &filename,
+            format: &request.format,
+            quality: &request.quality,
+            work_dir: &staging_dir,
+            cache_dir: job_cache.dir(),
+            download_id: &download_id,
+            info_json: Some(&metadata.info_path),
+            referer: site.referer.as_deref(),
+            cancel: &cancel,
         };
 
+        let result = match self.download_with_cli(&job).await {
+            Ok(output) => self
+                .promote_outputs(&staging_dir, output, policy == CollisionPolicy::Overwrite)
+                .map_err(|e| staging::keep_staging_dir(&staging_dir, e)),
+            Err(e) => Err(e),
+        };
+        staging::remove_staging_dir(&staging_dir);
+        drop(job_cache);
+
+        let mut history = DownloadHistory {
+            id: download_id,
+            url: request.url,
+            status: DownloadStatus::Completed,
+            downloaded_at: Utc::now(),
+            format: request.format,
+            quality: request.quality,
+            extractor: Some(extractor),
+            yt_dlp_version: self.yt_dlp_version(),
+            ..Default::default()
+        };
+        history.apply_video_info(&video_info);
+        match result {
+            Ok(output) => {
+                history.file_path = Some(output.file_path.to_string_lossy().to_string());
+                history.output_files = output
+                    .files
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect();
+                history.apply_media_details(&output.media);
+            }
+            Err(e) if DownloadError::is_cancellation(&e) => history.status = DownloadStatus::Cancelled,
+            Err(e) => {
+                history.status = DownloadStatus::Failed;
+                history.error = Some(DownloadFailure::from_error(&e));
+            }
+        }
+
         Ok(history)
     }
 
-    async fn download_with_cli(
+    pub(crate) fn skipped_history(
+        download_id: String,
+        title: String,
+        request: DownloadRequest,
+        existing: &Path,
+        extractor: String,
+    ) -> DownloadHistory {
+        println!("Skipping download, file already exists: {}", existing.display());
+        DownloadHistory {
+            id: download_id,
+            title,
+            url: request.url,
+            status: DownloadStatus::Skipped,
+            downloaded_at: Utc::now(),
+            file_path: Some(existing.to_string_lossy().to_string()),
+            format: request.format,
+            quality: request.quality,
+            output_files: vec![existing.to_string_lossy().to_string()],
+            hook_results: Vec::new(),
+            extractor: Some(extractor),
+            ..Default::default()
+        }
+    }
+
+    fn direct_downloader(&self) -> Result<DirectDownloader> {
+        Ok(DirectDownloader::new()?.with_progress_callback(self.progress_callback.clone()))
+    }
+
+    async fn download_direct(
         &self,
-        url: &str,
-        filename: &str,
-        format: &str,
-        quality: &str,
-    ) -> Result<PathBuf> {
-        println!("Downloading with CLI: {} as {} ({})", url, filename, format);
+        request: DownloadRequest,
+        download_id: String,
+        media: RemoteMedia,
+        cancel: &CancellationToken,
+    ) -> Result<DownloadHistory> {
+        let title = media.title();
+        let filename = match &request.filename {
+            Some(filename) => PathBuf::from(filename::sanitize_file_name(
+                filename,
+                self.filename_options.ascii_only,
+            )),
+            None => filename::render_filename(
+                &self.filename_options,
+                &FilenameContext {
+                    title: title.clone(),
+                    id: download_id.clone(),
+                    quality: request.quality.clone(),
+                    ext: media.extension.clone(),
+                    ..Default::default()
+                },
+            ),
+        };
+        let policy = request.collision_policy.unwrap_or_default();
+        let filename = match self.resolve_collision(&filename.to_string_lossy(), &media.extension, policy)? {
+            CollisionResolution::Proceed(filename) => filename,
+            CollisionResolution::Existing(existing) => {
+                return Ok(Self::skipped_history(
+                    download_id,
+                    title,
+                    request,
+                    &existing,
+                    DIRECT_EXTRACTOR.to_string(),
+                ));
+            }
+        };
 
-        let filename_without_ext = if let Some(stem) = std::path::Path::new(filename).file_stem() {
-            stem.to_string_lossy().to_string()
-        } else {
-            filename.to_string()
+        println!("Downloading direct media: {} as {}", media.url, filename);
+
+        if let Some(length) = media.content_length {
+            self.enforce_free_space(
+                &SizeEstimate {
+                    download_bytes: length,
+                    final_bytes: length,
+                },
+                &download_id,
+            )?;
+        }
+
+        // Registers the download as running, so clearing the cache leaves its
+        // staging folder and partial file alone.
+        let _job_cache = JobCache::create(&download_id)?;
+        let staging_dir = staging::create_staging_dir(&self.staging_root, &download_id)?;
+        let stem = Self::output_stem(&staging_dir, &filename);
+        let staged = stem.with_file_name(format!("{}.{}", Self::stem_name(&stem), media.extension));
+        // Another download of the same link holds the shared partial file.
+        let claim = PartialFile::claim(&cache::partial_downloads_dir()?, &media.url);
+        let partial = match &claim {
+            Some(claim) => claim.path().to_path_buf(),
+            None => staging_dir.join(format!("{}.part", Self::stem_name(&stem))),
         };
 
-        let output_dir = self._output_dir.to_string_lossy();
-        let output_template = format!("{}/{}.%(ext)s", output_dir, filename_without_ext);
-        
-        let cache_dir = self.get_cache_dir()?;
+        let downloader = self.direct_downloader()?;
+        let result = match downloader.download(&media, &partial, &staged, &download_id, cancel).await {
+            Ok(_) => self.promote_outputs(
+                &staging_dir,
+                DownloadOutput {
+                    file_path: staged.clone(),
+                    files: vec![staged.clone()],
+                    ..Default::default()
+                },
+                policy == CollisionPolicy::Overwrite,
+            )
+            .map_err(|e| staging::keep_staging_dir(&staging_dir, e)),
+            Err(e) => Err(e),
+        };
+        staging::remove_staging_dir(&staging_dir);
+
+        let (status, file_path, output_files, error) = match result {
+            Ok(output) => (
+                DownloadStatus::Completed,
+                Some(output.file_path.to_string_lossy().to_string()),
+                output
+                    .files
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect(),
+                None,
+            ),
+            Err(e) if DownloadError::is_cancellation(&e) => (DownloadStatus::Cancelled, None, Vec::new(), None),
+            Err(e) => {
+                eprintln!("Direct download failed: {}", e);
+                (DownloadStatus::Failed, None, Vec::new(), Some(DownloadFailure::from_error(&e)))
+            }
+        };
 
-        let yt_dlp_path = Self::find_yt_dlp_path()?;
-        let mut cmd = std::process::Command::new(&yt_dlp_path);
+        Ok(DownloadHistory {
+            id: download_id,
+            title,
+            url: request.url,
+            status,
+            downloaded_at: Utc::now(),
+            file_path,
+            format: media.extension,
+            quality: request.quality,
+            output_files,
+            hook_results: Vec::new(),
+            extractor: Some(DIRECT_EXTRACTOR.to_string()),
+            error,
+            ..Default::default()
+        })
+    }
+
+    async fn download_with_cli(&self, job: &CliDownload<'_>) -> Result<DownloadOutput> {
+        let CliDownload {
+            url,
+            filename,
+            format,
+            quality,
+            work_dir,
+            cache_dir: job_cache_dir,
+            info_json,
+            ..
+        } = *job;
+        println!("Downloading with CLI: {} as {} ({})", url, filename, format);
+
+        let output_template = Self::output_template(&Self::output_stem(Path::new(""), filename));
+        let report_path = job_cache_dir.join("output-report.txt");
         
-        if cfg!(target_os = "macos") {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            let extended_path = format!(
-                "{}:/usr/local/bin:/opt/homebrew/bin:/usr/bin:/bin:/opt/local/bin",
-                current_path
-            );
-            cmd.env("PATH", extended_path);
-        }
+        let cache_dir = cache::yt_dlp_cache_dir()?.to_string_lossy().to_string();
+
+        let yt_dlp_path = self.yt_dlp_binary()?;
+        let mut cmd = security::clean_command(&yt_dlp_path);
         
         cmd.args([
             "--cache-dir", &cache_dir,
             "--no-playlist",
             "--user-agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36",
-            "--referer", "https://www.youtube.com/",
             "--extractor-retries", "3",
             "--fragment-retries", "3",
         ]);
+        if let Some(referer) = job.referer {
+            cmd.args(["--referer", referer]);
+        }
+        cmd.args(Self::output_path_args(work_dir, job_cache_dir));
+        cmd.args(Self::output_report_args(&report_path));
+        cmd.args(Self::progress_args());
 
         match format {
             "mp3" => {
@@ -394,7 +1253,6 @@ impl YouTubeDownloader {
                     &self.get_audio_quality_param(quality),
                     "-o",
                     &output_template,
-                    url,
                 ]);
             }
             "wav" => {
@@ -406,7 +1264,6 @@ impl YouTubeDownloader {
                     "0",
                     "-o",
                     &output_template,
-                    url,
                 ]);
             }
             "mp4" => {
@@ -422,7 +1279,6 @@ impl YouTubeDownloader {
                     "--prefer-free-formats",
                     "-o",
                     &output_template,
-                    url,
                 ]);
             }
             "webm" => {
@@ -437,7 +1293,6 @@ impl YouTubeDownloader {
                     "--no-check-formats",
                     "-o",
                     &output_template,
-                    url,
                 ]);
             }
             _ => {
@@ -445,12 +1300,25 @@ impl YouTubeDownloader {
             }
         }
 
+        match info_json {
+            Some(info_json) => {
+                cmd.arg("--load-info-json").arg(info_json);
+            }
+            None => {
+                cmd.arg("--").arg(url);
+            }
+        }
+
         println!("Running command: {:?}", cmd);
-        let output = cmd.output()?;
+        let output = self.run_yt_dlp(cmd, job).await?;
 
         if !output.status.success() {
+            let _ = std::fs::remove_file(&report_path);
             let error = String::from_utf8_lossy(&output.stderr);
             eprintln!("yt-dlp error: {}", error);
+            if info_json.is_some() {
+                metadata_cache::invalidate(url);
+            }
             
             if error.contains("Requested format is not available") || error.contains("nsig extraction failed") {
                 eprintln!("Primary download failed, trying fallback strategy...");
@@ -459,47 +1327,479 @@ impl YouTubeDownloader {
                     eprintln!("Failed to list formats: {}", list_error);
                 }
                 
-                return self.fallback_download(url, filename, format, quality).await;
+                return self.fallback_download(job).await;
             }
             
             return Err(anyhow!("Download failed: {}", error));
         }
 
-        let expected_path = self
-            ._output_dir
-            .join(&format!("{}.{}", filename_without_ext, format));
+        let output = Self::read_output_report(&report_path)?;
+        println!("File downloaded successfully: {}", output.file_path.display());
+        Ok(output)
+    }
+
+    fn resolve_collision(
+        &self,
+        filename: &str,
+        format: &str,
+        policy: CollisionPolicy,
+    ) -> Result<CollisionResolution> {
+        Self::resolve_collision_in(&self._output_dir, filename, format, policy)
+    }
+
+    /// Applies `policy` when `filename` would clash with files already in
+    /// `output_dir`. Besides `<stem>.<format>` this counts every sibling named
+    /// `<stem>.*`, since merged formats, subtitles and thumbnails share the stem.
+    pub(crate) fn resolve_collision_in(
+        output_dir: &Path,
+        filename: &str,
+        format: &str,
+        policy: CollisionPolicy,
+    ) -> Result<CollisionResolution> {
+        let output_stem = Self::output_stem(output_dir, filename);
+        let stem_name = Self::stem_name(&output_stem);
+        let target = output_stem.with_file_name(format!("{}.{}", stem_name, format));
+        let siblings = Self::sibling_names(&output_stem);
+
+        if !Self::stem_in_use(&siblings, &stem_name) {
+            return Ok(CollisionResolution::Proceed(filename.to_string()));
+        }
+
+        match policy {
+            CollisionPolicy::Overwrite => {
+                println!("Overwriting existing file: {}", target.display());
+                Ok(CollisionResolution::Proceed(filename.to_string()))
+            }
+            CollisionPolicy::Skip if target.exists() => Ok(CollisionResolution::Existing(target)),
+            CollisionPolicy::Skip | CollisionPolicy::Rename => {
+                let relative_parent = Path::new(filename).parent().unwrap_or(Path::new(""));
+                for index in 1..=MAX_RENAME_ATTEMPTS {
+                    let candidate_name = filename::numbered_stem(&stem_name, index, format);
+                    if Self::stem_in_use(&siblings, &candidate_name) {
+                        continue;
+                    }
+                    let candidate_file = format!("{}.{}", candidate_name, format);
+                    println!(
+                        "File exists, renaming download to: {}",
+                        output_stem.with_file_name(&candidate_file).display()
+                    );
+                    return Ok(CollisionResolution::Proceed(
+                        relative_parent.join(candidate_file).to_string_lossy().to_string(),
+                    ));
+                }
+                Err(anyhow!(
+                    "No free file name for {} after {} attempts",
+                    target.display(),
+                    MAX_RENAME_ATTEMPTS
+                ))
+            }
+        }
+    }
+
+    fn sibling_names(output_stem: &Path) -> HashSet<String> {
+        output_stem
+            .parent()
+            .and_then(|dir| std::fs::read_dir(dir).ok())
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-        let result = if expected_path.exists() {
-            println!("File downloaded successfully: {}", expected_path.display());
-            Ok(expected_path)
+    fn stem_in_use(siblings: &HashSet<String>, stem_name: &str) -> bool {
+        let prefix = format!("{}.", stem_name);
+        siblings
+            .iter()
+            .any(|name| name == stem_name || name.starts_with(&prefix))
+    }
+
+    fn check_disk_space(
+        &self,
+        video_data: &serde_json::Value,
+        request: &DownloadRequest,
+        download_id: &str,
+    ) -> Result<()> {
+        if self.disk_space.policy == DiskSpacePolicy::Ignore {
+            return Ok(());
+        }
+
+        let max_height = self.get_video_height_param(&request.quality);
+        let Some(estimate) =
+            diskspace::estimate_download_size(video_data, &request.format, &request.quality, max_height)
+        else {
+            println!("Could not estimate download size, skipping disk space check");
+            return Ok(());
+        };
+        println!(
+            "Estimated download size: {} bytes ({} bytes final)",
+            estimate.download_bytes, estimate.final_bytes
+        );
+
+        self.enforce_free_space(&estimate, download_id)
+    }
+
+    fn enforce_free_space(&self, estimate: &SizeEstimate, download_id: &str) -> Result<()> {
+        if self.disk_space.policy == DiskSpacePolicy::Ignore {
+            return Ok(());
+        }
+
+        let Some(shortage) = diskspace::check_free_space(
+            estimate,
+            &self.staging_root,
+            &self._output_dir,
+            self.disk_space.min_free_bytes,
+        ) else {
+            return Ok(());
+        };
+
+        let error = DownloadError::DiskFull {
+            path: shortage.path,
+            required: shortage.required,
+            available: shortage.available,
+        };
+
+        match self.disk_space.policy {
+            DiskSpacePolicy::Refuse => Err(error.into()),
+            _ => {
+                eprintln!("Warning: {}", error);
+                let mut progress = DownloadProgress::new(download_id, DownloadStatus::Downloading, 0.0);
+                progress.warning = Some(error.to_string());
+                self.report_progress(progress);
+                Ok(())
+            }
+        }
+    }
+
+    fn progress_args() -> Vec<String> {
+        vec![
+            "--newline".to_string(),
+            "--progress-template".to_string(),
+            format!(
+                "download:{}%(progress.downloaded_bytes)s|%(progress.total_bytes)s|%(progress.total_bytes_estimate)s|%(progress._speed_str)s|%(progress._eta_str)s",
+                PROGRESS_PREFIX
+            ),
+        ]
+    }
+
+    fn parse_progress_line(line: &str, download_id: &str) -> Option<DownloadProgress> {
+        let fields: Vec<&str> = line.strip_prefix(PROGRESS_PREFIX)?.split('|').collect();
+        let number = |index: usize| -> Option<f64> {
+            fields.get(index).and_then(|v| v.trim().parse::<f64>().ok())
+        };
+        let text = |index: usize| -> Option<String> {
+            fields
+                .get(index)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty() && *v != "NA" && *v != "Unknown")
+                .map(|v| v.to_string())
+        };
+
+        let downloaded = number(0).unwrap_or(0.0);
+        let total = number(1).or_else(|| number(2)).unwrap_or(0.0);
+        let percent = if total > 0.0 {
+            (downloaded / total * 100.0).min(100.0)
         } else {
-            if let Ok(entries) = std::fs::read_dir(&self._output_dir) {
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        let path = entry.path();
-                        if let Some(file_stem) = path.file_stem() {
-                            if file_stem
-                                .to_string_lossy()
-                                .starts_with(&filename_without_ext)
-                            {
-                                println!("Found downloaded file: {}", path.display());
-                                return Ok(path);
-                            }
+            0.0
+        };
+
+        let mut progress = DownloadProgress::new(download_id, DownloadStatus::Downloading, percent);
+        progress.speed = text(3);
+        progress.eta = text(4);
+        Some(progress)
+    }
+
+    async fn run_yt_dlp(
+        &self,
+        cmd: std::process::Command,
+        job: &CliDownload<'_>,
+    ) -> Result<std::process::Output> {
+        let recorded_args = fixtures::recording_dir().map(|_| fixtures::command_args(&cmd));
+        let mut cmd = tokio::process::Command::from(cmd);
+        cmd.stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = cmd.spawn()?;
+        let pid = child.id();
+
+        let mut stderr = child.stderr.take();
+        let stderr_task = tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            if let Some(stderr) = stderr.as_mut() {
+                let _ = stderr.read_to_end(&mut buffer).await;
+            }
+            buffer
+        });
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Failed to capture yt-dlp output"))?;
+        let mut lines = BufReader::new(stdout).lines();
+        let mut collected_stdout = Vec::new();
+        let mut raw_stdout = Vec::new();
+        let mut monitor = tokio::time::interval(DISK_CHECK_INTERVAL);
+        let mut disk = DiskMonitor::default();
+        let mut last_progress = 0.0;
+
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line? else {
+                        break;
+                    };
+                    if recorded_args.is_some() {
+                        raw_stdout.extend_from_slice(line.as_bytes());
+                        raw_stdout.push(b'\n');
+                    }
+                    match Self::parse_progress_line(&line, job.download_id) {
+                        Some(progress) => {
+                            last_progress = progress.progress;
+                            self.report_progress(progress);
+                        }
+                        None => {
+                            collected_stdout.extend_from_slice(line.as_bytes());
+                            collected_stdout.push(b'\n');
                         }
                     }
                 }
+                _ = monitor.tick() => {
+                    self.monitor_disk_space(&mut child, pid, job, &mut disk, last_progress)?;
+                }
+                _ = job.cancel.cancelled() => {
+                    println!("Cancelling download {}", job.download_id);
+                    #[cfg(unix)]
+                    if let Some(pid) = pid {
+                        diskspace::kill_process_group(pid);
+                    }
+                    let _ = child.kill().await;
+                    return Err(DownloadError::Cancelled.into());
+                }
             }
-            Err(anyhow!(
-                "Downloaded file not found at: {}",
-                expected_path.display()
-            ))
+        }
+
+        #[cfg(unix)]
+        if disk.paused_since.is_some() {
+            if let Some(pid) = pid {
+                diskspace::resume_process_group(pid);
+            }
+        }
+
+        let status = child.wait().await?;
+        let stderr = stderr_task.await.unwrap_or_default();
+
+        if let Some(args) = recorded_args {
+            fixtures::record(
+                &args,
+                &std::process::Output {
+                    status,
+                    stdout: raw_stdout,
+                    stderr: stderr.clone(),
+                },
+            );
+        }
+
+        Ok(std::process::Output {
+            status,
+            stdout: collected_stdout,
+            stderr,
+        })
+    }
+
+    /// Checks the volumes yt-dlp writes to while it runs. Under `Refuse` a
+    /// shortage pauses the process until space is back, failing the download
+    /// after `MAX_DISK_PAUSE`; under `Warn` it is only reported.
+    fn monitor_disk_space(
+        &self,
+        child: &mut tokio::process::Child,
+        pid: Option<u32>,
+        job: &CliDownload<'_>,
+        state: &mut DiskMonitor,
+        last_progress: f64,
+    ) -> Result<()> {
+        let min_free = self.disk_space.min_free_bytes;
+        if self.disk_space.policy == DiskSpacePolicy::Ignore || min_free == 0 {
+            return Ok(());
+        }
+
+        let available = [job.cache_dir, job.work_dir]
+            .iter()
+            .filter_map(|dir| diskspace::available_space(dir))
+            .min();
+        let Some(available) = available else {
+            return Ok(());
         };
+        let error = DownloadError::DiskFull {
+            path: job.work_dir.to_string_lossy().to_string(),
+            required: min_free,
+            available,
+        };
+
+        if let Some(since) = state.paused_since {
+            if available >= min_free.saturating_mul(2) {
+                #[cfg(unix)]
+                if let Some(pid) = pid {
+                    diskspace::resume_process_group(pid);
+                }
+                state.paused_since = None;
+                println!("Disk space recovered, resuming download {}", job.download_id);
+                self.report_progress(DownloadProgress::new(
+                    job.download_id,
+                    DownloadStatus::Downloading,
+                    last_progress,
+                ));
+            } else if since.elapsed() >= MAX_DISK_PAUSE {
+                eprintln!(
+                    "Stopping download {} after {} minutes without disk space: {}",
+                    job.download_id,
+                    MAX_DISK_PAUSE.as_secs() / 60,
+                    error
+                );
+                Self::stop_process(child, pid);
+                return Err(error.into());
+            }
+            return Ok(());
+        }
 
-        if let Err(e) = self.cleanup_cache(&cache_dir) {
-            eprintln!("Warning: Failed to cleanup cache: {}", e);
+        if available >= min_free {
+            state.warned = false;
+            return Ok(());
+        }
+
+        if self.disk_space.policy == DiskSpacePolicy::Warn {
+            if !state.warned {
+                eprintln!("Warning: download {}: {}", job.download_id, error);
+                let mut progress =
+                    DownloadProgress::new(job.download_id, DownloadStatus::Downloading, last_progress);
+                progress.warning = Some(error.to_string());
+                self.report_progress(progress);
+                state.warned = true;
+            }
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        if let Some(pid) = pid {
+            if diskspace::suspend_process_group(pid) {
+                eprintln!("Pausing download {}: {}", job.download_id, error);
+                let mut progress =
+                    DownloadProgress::new(job.download_id, DownloadStatus::Paused, last_progress);
+                progress.warning = Some(error.to_string());
+                self.report_progress(progress);
+                state.paused_since = Some(Instant::now());
+                return Ok(());
+            }
         }
 
-        result
+        eprintln!("Stopping download {}: {}", job.download_id, error);
+        Self::stop_process(child, pid);
+        Err(error.into())
+    }
+
+    fn stop_process(child: &mut tokio::process::Child, pid: Option<u32>) {
+        #[cfg(unix)]
+        if let Some(pid) = pid {
+            diskspace::kill_process_group(pid);
+        }
+        let _ = pid;
+        let _ = child.start_kill();
+    }
+
+    fn output_path_args(work_dir: &Path, temp_dir: &Path) -> Vec<String> {
+        vec![
+            "--paths".to_string(),
+            format!("home:{}", work_dir.to_string_lossy()),
+            "--paths".to_string(),
+            format!("temp:{}", temp_dir.to_string_lossy()),
+        ]
+    }
+
+    fn output_report_args(report_path: &Path) -> Vec<String> {
+        let report_file = report_path.to_string_lossy().replace('%', "%%");
+        [
+            format!("after_move:{}%(filepath)s", REPORT_FILEPATH),
+            format!("after_move:{}%(__files_to_move)j", REPORT_MOVED),
+            format!("after_move:{}%(.{{resolution,vcodec,acodec}})j", REPORT_MEDIA),
+            format!("post_process:{}%(__files_to_merge)j", REPORT_MERGED),
+        ]
+        .into_iter()
+        .flat_map(|template| ["--print-to-file".to_string(), template, report_file.clone()])
+        .collect()
+    }
+
+    fn read_output_report(report_path: &Path) -> Result<DownloadOutput> {
+        let report = std::fs::read_to_string(report_path)
+            .map_err(|e| anyhow!("Failed to read yt-dlp output report: {}", e));
+        let _ = std::fs::remove_file(report_path);
+
+        let output = parse_output_report(&report?)?;
+        if !output.file_path.exists() {
+            return Err(anyhow!(
+                "yt-dlp reported {} but the file does not exist",
+                output.file_path.display()
+            ));
+        }
+
+        for intermediate in output.intermediates.iter().filter(|p| p.exists()) {
+            println!("Removing leftover intermediate file: {}", intermediate.display());
+            if let Err(e) = std::fs::remove_file(intermediate) {
+                eprintln!("Warning: Failed to remove {:?}: {}", intermediate, e);
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn output_stem(base_dir: &Path, filename: &str) -> PathBuf {
+        base_dir.join(filename::strip_media_extension(Path::new(filename)))
+    }
+
+    fn promote_outputs(
+        &self,
+        staging_dir: &Path,
+        output: DownloadOutput,
+        overwrite: bool,
+    ) -> Result<DownloadOutput> {
+        let mut promoted = DownloadOutput {
+            file_path: output.file_path.clone(),
+            files: Vec::new(),
+            intermediates: output.intermediates,
+            media: output.media,
+        };
+
+        for file in &output.files {
+            let Ok(relative) = file.strip_prefix(staging_dir) else {
+                promoted.files.push(file.clone());
+                continue;
+            };
+
+            let destination = self._output_dir.join(relative);
+            staging::move_file(file, &destination, overwrite)?;
+            println!("Moved {} to {}", file.display(), destination.display());
+
+            if *file == output.file_path {
+                promoted.file_path = destination.clone();
+            }
+            promoted.files.push(destination);
+        }
+
+        Ok(promoted)
+    }
+
+    fn stem_name(output_stem: &Path) -> String {
+        output_stem
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    fn output_template(output_stem: &Path) -> String {
+        format!("{}.%(ext)s", output_stem.to_string_lossy().replace('%', "%%"))
     }
 
     fn get_audio_quality_param(&self, quality: &str) -> String {
@@ -561,25 +1861,18 @@ impl YouTubeDownloader {
     }
 
     async fn list_available_formats(&self, url: &str) -> Result<()> {
-        let yt_dlp_path = Self::find_yt_dlp_path()?;
-        let mut cmd = std::process::Command::new(&yt_dlp_path);
-        
-        if cfg!(target_os = "macos") {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            let extended_path = format!(
-                "{}:/usr/local/bin:/opt/homebrew/bin:/usr/bin:/bin:/opt/local/bin",
-                current_path
-            );
-            cmd.env("PATH", extended_path);
-        }
+        let yt_dlp_path = self.yt_dlp_binary()?;
+        let mut cmd = security::clean_command(&yt_dlp_path);
         
         cmd.args([
             "--list-formats",
             "--no-playlist",
+            "--",
             url,
         ]);
 
         let output = cmd.output()?;
+        fixtures::record(&fixtures::command_args(&cmd), &output);
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
         
@@ -592,42 +1885,32 @@ impl YouTubeDownloader {
         Ok(())
     }
 
-    async fn fallback_download(
-        &self,
-        url: &str,
-        filename: &str,
-        format: &str,
-        _quality: &str,
-    ) -> Result<PathBuf> {
+    async fn fallback_download(&self, job: &CliDownload<'_>) -> Result<DownloadOutput> {
+        let CliDownload {
+            url,
+            filename,
+            format,
+            work_dir,
+            cache_dir: job_cache_dir,
+            ..
+        } = *job;
         eprintln!("Attempting fallback download with simpler parameters");
 
-        let filename_without_ext = if let Some(stem) = std::path::Path::new(filename).file_stem() {
-            stem.to_string_lossy().to_string()
-        } else {
-            filename.to_string()
-        };
-
-        let output_dir = self._output_dir.to_string_lossy();
-        let output_template = format!("{}/{}.%(ext)s", output_dir, filename_without_ext);
+        let output_template = Self::output_template(&Self::output_stem(Path::new(""), filename));
+        let report_path = job_cache_dir.join("fallback-report.txt");
         
-        let cache_dir = self.get_cache_dir()?;
-        let yt_dlp_path = Self::find_yt_dlp_path()?;
-        let mut cmd = std::process::Command::new(&yt_dlp_path);
-        
-        if cfg!(target_os = "macos") {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            let extended_path = format!(
-                "{}:/usr/local/bin:/opt/homebrew/bin:/usr/bin:/bin:/opt/local/bin",
-                current_path
-            );
-            cmd.env("PATH", extended_path);
-        }
+        let cache_dir = cache::yt_dlp_cache_dir()?.to_string_lossy().to_string();
+        let yt_dlp_path = self.yt_dlp_binary()?;
+        let mut cmd = security::clean_command(&yt_dlp_path);
         
         cmd.args([
             "--cache-dir", &cache_dir,
             "--no-playlist",
             "--user-agent", "Mozilla/5.0 (compatible; yt-dlp)",
         ]);
+        cmd.args(Self::output_path_args(work_dir, job_cache_dir));
+        cmd.args(Self::output_report_args(&report_path));
+        cmd.args(Self::progress_args());
 
         match format {
             "mp3" => {
@@ -636,7 +1919,6 @@ impl YouTubeDownloader {
                     "--audio-format", "mp3",
                     "--audio-quality", "192K",
                     "-o", &output_template,
-                    url,
                 ]);
             }
             "wav" => {
@@ -645,7 +1927,6 @@ impl YouTubeDownloader {
                     "--audio-format", "wav",
                     "--audio-quality", "0",
                     "-o", &output_template,
-                    url,
                 ]);
             }
             "mp4" => {
@@ -653,7 +1934,6 @@ impl YouTubeDownloader {
                     "-f", "best/worst",
                     "--recode-video", "mp4",
                     "-o", &output_template,
-                    url,
                 ]);
             }
             "webm" => {
@@ -661,127 +1941,73 @@ impl YouTubeDownloader {
                     "-f", "best/worst",
                     "--recode-video", "webm",
                     "-o", &output_template,
-                    url,
                 ]);
             }
             _ => {
                 return Err(anyhow!("Unsupported format for fallback: {}", format));
             }
         }
+        cmd.arg("--").arg(url);
 
         eprintln!("Running fallback command: {:?}", cmd);
-        let output = cmd.output()?;
+        let output = self.run_yt_dlp(cmd, job).await?;
 
         if !output.status.success() {
+            let _ = std::fs::remove_file(&report_path);
             let error = String::from_utf8_lossy(&output.stderr);
             eprintln!("Fallback download also failed: {}", error);
             return Err(anyhow!("Fallback download failed: {}", error));
         }
 
-        let expected_path = self._output_dir.join(&format!("{}.{}", filename_without_ext, format));
-        if expected_path.exists() {
-            eprintln!("Fallback download successful: {}", expected_path.display());
-            return Ok(expected_path);
-        }
+        let output = Self::read_output_report(&report_path)?;
+        eprintln!("Fallback download successful: {}", output.file_path.display());
+        Ok(output)
+    }
+    
+    pub fn validate_url(&self, url: &str) -> bool {
+        urls::is_supported_url(url)
+    }
 
-        if let Ok(entries) = std::fs::read_dir(&self._output_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if let Some(file_stem) = path.file_stem() {
-                        if file_stem.to_string_lossy().starts_with(&filename_without_ext) {
-                            eprintln!("Found fallback download file: {}", path.display());
-                            return Ok(path);
-                        }
-                    }
-                }
-            }
+    /// Resolves the enabled site entry for a URL. YouTube URLs are recognized
+    /// locally; anything else is probed with yt-dlp, whose metadata is cached
+    /// for the download that usually follows.
+    pub async fn identify_site(&self, url: &str, extract: bool) -> Result<SiteConfig> {
+        if urls::is_supported_url(url) {
+            return self.enabled_site(sites::YOUTUBE_EXTRACTOR);
+        }
+        if let Some(site) = sites::find_site_for_url(&self.sites, url) {
+            return Self::require_enabled(site);
+        }
+        if !extract {
+            return Err(anyhow!("{} is not on a known site", url));
         }
 
-        Err(anyhow!("Fallback download file not found"))
-    }
-    
-    fn get_cache_dir(&self) -> Result<String> {
-        let temp_dir = std::env::temp_dir();
-        let cache_dir = temp_dir.join("snapper-cache");
-        
-        std::fs::create_dir_all(&cache_dir)
-            .map_err(|e| anyhow!("Failed to create cache directory: {}", e))?;
-            
-        Ok(cache_dir.to_string_lossy().to_string())
+        let metadata = self.video_metadata(url, false).await?;
+        let extractor = Self::extractor_key(&metadata.data)
+            .ok_or_else(|| anyhow!("yt-dlp did not report an extractor for {}", url))?;
+        self.enabled_site(&extractor)
     }
 
-    fn cleanup_cache(&self, cache_dir: &str) -> Result<()> {
-        self.cleanup_single_cache_dir(cache_dir)?;
-        
-        let output_cache_dir = self._output_dir.join("cache");
-        if output_cache_dir.exists() {
-            let output_cache_str = output_cache_dir.to_string_lossy().to_string();
-            println!("Also cleaning output directory cache: {}", output_cache_str);
-            self.cleanup_single_cache_dir(&output_cache_str)?;
+    fn enabled_site(&self, extractor: &str) -> Result<SiteConfig> {
+        match sites::find_site(&self.sites, extractor) {
+            Some(site) => Self::require_enabled(site),
+            None => Err(anyhow!("Extractor '{}' is not in the supported sites list", extractor)),
         }
-        
-        Ok(())
     }
-    
-    fn cleanup_single_cache_dir(&self, cache_dir: &str) -> Result<()> {
-        let cache_path = std::path::Path::new(cache_dir);
-        
-        println!("Cleaning up cache directory: {}", cache_dir);
-        
-        if cache_path.exists() && cache_path.is_dir() {
-            println!("Cache directory exists, cleaning contents...");
-            
-            if let Ok(entries) = std::fs::read_dir(cache_path) {
-                let entries: Vec<_> = entries.collect();
-                println!("Found {} entries in cache directory", entries.len());
-                
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        let path = entry.path();
-                        println!("Removing cache entry: {:?}", path);
-                        
-                        if path.is_file() {
-                            match std::fs::remove_file(&path) {
-                                Ok(_) => println!("Successfully removed cache file: {:?}", path),
-                                Err(e) => eprintln!("Failed to remove cache file {:?}: {}", path, e),
-                            }
-                        } else if path.is_dir() {
-                            match std::fs::remove_dir_all(&path) {
-                                Ok(_) => println!("Successfully removed cache directory: {:?}", path),
-                                Err(e) => eprintln!("Failed to remove cache directory {:?}: {}", path, e),
-                            }
-                        }
-                    }
-                }
-            } else {
-                eprintln!("Failed to read cache directory: {}", cache_dir);
-            }
-            
-            if let Ok(entries) = std::fs::read_dir(cache_path) {
-                let remaining: Vec<_> = entries.collect();
-                if remaining.is_empty() {
-                    println!("Cache directory is empty, removing it...");
-                    match std::fs::remove_dir(cache_path) {
-                        Ok(_) => println!("Successfully removed empty cache directory"),
-                        Err(e) => eprintln!("Failed to remove empty cache directory: {}", e),
-                    }
-                } else {
-                    eprintln!("Warning: {} entries still remain in cache directory", remaining.len());
-                }
-            }
+
+    fn require_enabled(site: &SiteConfig) -> Result<SiteConfig> {
+        if site.enabled {
+            Ok(site.clone())
         } else {
-            println!("Cache directory does not exist or is not a directory: {}", cache_dir);
+            Err(anyhow!("Downloads from {} are disabled in settings", site.name))
         }
-        
-        Ok(())
     }
 
-    pub fn validate_url(&self, url: &str) -> bool {
-        url.contains("youtube.com/watch")
-            || url.contains("youtu.be/")
-            || url.contains("youtube.com/playlist")
-            || url.contains("youtube.com/shorts/")
+    fn extractor_key(video_data: &serde_json::Value) -> Option<String> {
+        video_data["extractor_key"]
+            .as_str()
+            .or_else(|| video_data["extractor"].as_str())
+            .map(|s| s.to_string())
     }
 
     pub async fn check_dependencies() -> Result<()> {
@@ -792,3 +2018,54 @@ impl YouTubeDownloader {
         Ok(())
     }
 }
+
+#[async_trait::async_trait]
+impl MediaBackend for YouTubeDownloader {
+    fn name(&self) -> &'static str {
+        "yt-dlp-cli"
+    }
+
+    async fn check_dependencies(&self) -> Result<()> {
+        match &self.binaries {
+            Some(binaries) if binaries.yt_dlp.exists() && binaries.ffmpeg.exists() => Ok(()),
+            _ => Self::check_dependencies().await,
+        }
+    }
+
+    fn ffmpeg_path(&self) -> Result<PathBuf> {
+        self.ffmpeg_binary()
+    }
+
+    async fn probe(&self, url: &str, refresh: bool) -> Result<VideoInfo> {
+        self.get_video_info(url, refresh).await
+    }
+
+    async fn list_formats(&self, url: &str) -> Result<Vec<FormatInfo>> {
+        let metadata = self.video_metadata(url, false).await?;
+        Ok(FormatInfo::list_from_video_data(&metadata.data))
+    }
+
+    async fn identify_site(&self, url: &str, extract: bool) -> Result<SiteConfig> {
+        YouTubeDownloader::identify_site(self, url, extract).await
+    }
+
+    async fn probe_direct(&self, url: &str) -> Result<bool> {
+        if !self.direct_downloads {
+            return Ok(false);
+        }
+        Ok(self.direct_downloader()?.probe(url).await?.is_some())
+    }
+
+    fn cached_video_info(&self, url: &str) -> Option<VideoInfo> {
+        metadata_cache::get(url, self.metadata_ttl).map(|cached| Self::parse_video_info(url, &cached.data))
+    }
+
+    async fn download(
+        &self,
+        request: DownloadRequest,
+        download_id: String,
+        cancel: CancellationToken,
+    ) -> Result<DownloadHistory> {
+        self.download_video(request, download_id, cancel).await
+    }
+}
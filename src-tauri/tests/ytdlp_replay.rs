@@ -0,0 +1,162 @@
+//! Runs the yt-dlp backend against the fixtures in `tests/fixtures/ytdlp`,
+//! served by the `ytdlp-replay` stand-in, so no network or yt-dlp install is
+//! needed. Replay is only honoured in debug builds.
+#![cfg(debug_assertions)]
+
+use snapper_lib::backend::{Binaries, CancellationToken};
+use snapper_lib::diskspace::{DiskSpaceOptions, DiskSpacePolicy};
+use snapper_lib::fixtures;
+use snapper_lib::metadata_cache;
+use snapper_lib::youtube::{
+    DownloadHistory, DownloadProgress, DownloadRequest, DownloadStatus, ErrorCategory,
+    YouTubeDownloader,
+};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+/// Points yt-dlp lookups at the stand-in and the fixtures it serves. yt-dlp
+/// is started with a clean environment that only forwards the fixtures
+/// directory when it is set here.
+fn replay_fixtures() {
+    static REPLAY: Once = Once::new();
+    REPLAY.call_once(|| {
+        std::env::set_var(
+            fixtures::REPLAY_ENV,
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("ytdlp"),
+        );
+        std::env::set_var(fixtures::REPLAY_BIN_ENV, env!("CARGO_BIN_EXE_ytdlp-replay"));
+    });
+}
+
+struct Harness {
+    root: PathBuf,
+    progress: Arc<Mutex<Vec<DownloadProgress>>>,
+    downloader: YouTubeDownloader,
+}
+
+impl Harness {
+    fn new() -> Self {
+        replay_fixtures();
+
+        let root = std::env::temp_dir().join(format!("snapper-replay-{}", uuid::Uuid::new_v4()));
+        let binaries = Binaries {
+            yt_dlp: PathBuf::from(env!("CARGO_BIN_EXE_ytdlp-replay")),
+            ffmpeg: PathBuf::from("ffmpeg"),
+            yt_dlp_version: Some("2025.06.09".to_string()),
+        };
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let sink = progress.clone();
+        let downloader = YouTubeDownloader::with_binaries(root.join("output"), binaries)
+            .with_staging_root(root.join("staging"))
+            .with_metadata_ttl(Duration::ZERO)
+            .with_disk_space_options(DiskSpaceOptions {
+                policy: DiskSpacePolicy::Ignore,
+                ..Default::default()
+            })
+            .with_progress_callback(Arc::new(move |update| sink.lock().unwrap().push(update)));
+
+        Self {
+            root,
+            progress,
+            downloader,
+        }
+    }
+
+    async fn download(&self, url: &str) -> DownloadHistory {
+        let request = DownloadRequest {
+            url: url.to_string(),
+            format: "mp4".to_string(),
+            quality: "high".to_string(),
+            output_path: self.root.join("output").to_string_lossy().to_string(),
+            filename: None,
+            pipeline: None,
+            collision_policy: None,
+        };
+        self.downloader
+            .download_video(request, uuid::Uuid::new_v4().to_string(), CancellationToken::new())
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[tokio::test]
+async fn test_check_dependencies() {
+    replay_fixtures();
+    let result = YouTubeDownloader::check_dependencies().await;
+    assert!(result.is_ok(), "Dependencies should be available: {:?}", result);
+
+    let yt_dlp = PathBuf::from(env!("CARGO_BIN_EXE_ytdlp-replay"));
+    let version = YouTubeDownloader::query_yt_dlp_version(&yt_dlp);
+    assert_eq!(version.as_deref(), Some("ytdlp-replay (fixture replay)"));
+}
+
+#[tokio::test]
+async fn test_get_video_info() {
+    replay_fixtures();
+    let root = std::env::temp_dir().join(format!("snapper-replay-{}", uuid::Uuid::new_v4()));
+    let downloader = YouTubeDownloader::new(root).expect("Failed to create downloader");
+
+    let test_url = "https://www.youtube.com/watch?v=fixtureOk01";
+    let video_info = downloader.get_video_info(test_url, true).await.unwrap();
+    assert_eq!(video_info.url, test_url);
+    assert_eq!(video_info.id, "fixtureOk01");
+    assert_eq!(video_info.title, "Replayed Clip");
+    assert_eq!(video_info.uploader.as_deref(), Some("Fixture Channel"));
+    assert_eq!(video_info.duration, Some(42));
+}
+
+#[tokio::test]
+async fn test_replayed_download() {
+    let harness = Harness::new();
+    let history = harness.download("https://www.youtube.com/watch?v=fixtureOk01").await;
+
+    assert!(matches!(history.status, DownloadStatus::Completed));
+    assert_eq!(history.title, "Replayed Clip");
+    assert_eq!(history.yt_dlp_version.as_deref(), Some("2025.06.09"));
+    let file_path = PathBuf::from(history.file_path.unwrap());
+    assert_eq!(file_path, harness.root.join("output").join("Replayed Clip.mp4"));
+    assert!(file_path.exists());
+    assert_eq!(history.output_files.len(), 1);
+    assert!(!harness.root.join("output").join("Replayed Clip.f137.mp4").exists());
+
+    let progress = harness.progress.lock().unwrap();
+    assert_eq!(progress.last().map(|p| p.progress), Some(100.0));
+}
+
+#[tokio::test]
+async fn test_replayed_format_fallback() {
+    let harness = Harness::new();
+    let history = harness.download("https://www.youtube.com/watch?v=fixtureFb02").await;
+
+    assert!(matches!(history.status, DownloadStatus::Completed));
+    assert!(history.error.is_none());
+    let file_path = PathBuf::from(history.file_path.unwrap());
+    assert_eq!(file_path, harness.root.join("output").join("Fallback Clip.mp4"));
+    assert!(file_path.exists());
+}
+
+#[tokio::test]
+async fn test_replayed_failure_is_classified() {
+    let harness = Harness::new();
+    let url = "https://www.youtube.com/watch?v=fixtureEr03";
+    let history = harness.download(url).await;
+
+    assert!(matches!(history.status, DownloadStatus::Failed));
+    assert!(history.file_path.is_none());
+    let failure = history.error.unwrap();
+    assert_eq!(failure.category, ErrorCategory::Restricted);
+    // Only yt-dlp's ERROR lines are kept, not its warnings.
+    assert_eq!(
+        failure.message,
+        "ERROR: [youtube] fixtureEr03: Private video. Sign in if you've been granted access to this video"
+    );
+    // The cached info JSON may be what broke the download, so it is dropped.
+    assert!(metadata_cache::get(url, Duration::from_secs(3600)).is_none());
+}